@@ -0,0 +1,74 @@
+//! Encodes a text map into a binary level for the flash slot (see `levels::FLASH_ADDRESS`).
+//!
+//! ```text
+//! cargo run --bin encode-level --features tools --target x86_64-unknown-linux-gnu -- \
+//!     <map file> <level file> [<barrel|lamp> <x> <y>]...
+//! ```
+//!
+//! The player starts where the map's start marker is, and each sprite is given by its kind and
+//! its position in tiles.
+
+use std::{env, fs, process};
+
+use raycaster_embedded_rs::levels;
+use raycaster_embedded_rs::raycaster::{parse_map, Angle, Fixed, Level, MapStorage, Spawn};
+
+/// Cells in the largest level that fits in the flash slot, with room to spare for spawns.
+const MAX_CELLS: usize = 128 * 128;
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.len() < 2 {
+        usage();
+    }
+
+    let text = fs::read_to_string(&args[0])
+        .unwrap_or_else(|e| fail(&format!("can't read {}: {}", args[0], e)));
+    let mut storage = Box::new(MapStorage::<MAX_CELLS>::new());
+    let (map, player) =
+        parse_map(&text, &mut storage).unwrap_or_else(|e| fail(&format!("{}: {}", args[0], e)));
+
+    let mut spawns = vec![Spawn::new(Spawn::PLAYER, player.x, player.y, player.angle)];
+    for sprite in args[2..].chunks(3) {
+        let [kind, x, y] = sprite else { usage() };
+        let kind = match kind.as_str() {
+            "barrel" => levels::BARREL,
+            "lamp" => levels::LAMP,
+            kind => fail(&format!("unknown sprite kind `{}`", kind)),
+        };
+        spawns.push(Spawn::new(
+            kind,
+            parse_tiles(x),
+            parse_tiles(y),
+            Angle::ZERO,
+        ));
+    }
+
+    let mut bytes = vec![0; Level::encoded_len(&map, &spawns)];
+    Level::encode(&map, &spawns, &mut bytes).unwrap_or_else(|e| fail(&format!("{:?}", e)));
+    if bytes.len() > levels::FLASH_MAX_LEN {
+        fail(&format!(
+            "level is {} bytes, but the flash slot only holds {}",
+            bytes.len(),
+            levels::FLASH_MAX_LEN
+        ));
+    }
+    fs::write(&args[1], &bytes)
+        .unwrap_or_else(|e| fail(&format!("can't write {}: {}", args[1], e)));
+}
+
+fn parse_tiles(text: &str) -> Fixed {
+    match text.parse::<f32>() {
+        Ok(tiles) => Fixed::from_f32(tiles),
+        Err(_) => fail(&format!("`{}` isn't a position", text)),
+    }
+}
+
+fn usage() -> ! {
+    fail("usage: encode-level <map file> <level file> [<barrel|lamp> <x> <y>]...")
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("{}", message);
+    process::exit(1)
+}
@@ -0,0 +1,359 @@
+//! Maps buttons to game actions, so control schemes can be picked from presets, remapped and
+//! saved with the settings.
+
+use crate::input::{ButtonId, Input};
+use crate::raycaster::MoveInput;
+
+/// Version of the [`Controls::to_bytes`] format.
+const VERSION: u8 = 1;
+/// Marks an action without a binding.
+const UNBOUND: u8 = 0xff;
+/// Set in a modifier byte when the binding needs the modifier released rather than held.
+const MODIFIER_RELEASED: u8 = 0x80;
+
+/// Something the player can do.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    Forward,
+    Back,
+    TurnLeft,
+    TurnRight,
+    StrafeLeft,
+    StrafeRight,
+    Use,
+    Fire,
+    Menu,
+}
+
+impl Action {
+    pub const COUNT: usize = 9;
+
+    /// Every action, in declaration order.
+    pub const ALL: [Action; Action::COUNT] = [
+        Action::Forward,
+        Action::Back,
+        Action::TurnLeft,
+        Action::TurnRight,
+        Action::StrafeLeft,
+        Action::StrafeRight,
+        Action::Use,
+        Action::Fire,
+        Action::Menu,
+    ];
+}
+
+/// Another button that must be held, or must not be held, for a [`Binding`] to apply.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Modifier {
+    None,
+    Held(ButtonId),
+    Released(ButtonId),
+}
+
+/// A button that triggers an action, subject to a modifier.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Binding {
+    pub button: ButtonId,
+    pub modifier: Modifier,
+}
+
+impl Binding {
+    pub const fn new(button: ButtonId) -> Self {
+        Self {
+            button,
+            modifier: Modifier::None,
+        }
+    }
+
+    /// Creates a binding that only applies while `modifier` is held.
+    pub const fn with(button: ButtonId, modifier: ButtonId) -> Self {
+        Self {
+            button,
+            modifier: Modifier::Held(modifier),
+        }
+    }
+
+    /// Creates a binding that only applies while `modifier` isn't held.
+    pub const fn without(button: ButtonId, modifier: ButtonId) -> Self {
+        Self {
+            button,
+            modifier: Modifier::Released(modifier),
+        }
+    }
+
+    fn modifier_applies(&self, input: &Input) -> bool {
+        match self.modifier {
+            Modifier::None => true,
+            Modifier::Held(button) => input.is_down(button),
+            Modifier::Released(button) => !input.is_down(button),
+        }
+    }
+}
+
+/// Built-in control schemes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Preset {
+    /// Up and down walk, left and right turn. A fires, B uses and Y opens the menu.
+    Classic,
+    /// Like [`Preset::Classic`], but left and right strafe while A is held. B fires, X uses and
+    /// Y opens the menu.
+    HoldToStrafe,
+    /// The D-pad walks and strafes, X and Y turn. A fires, B uses, and A and B together open the
+    /// menu.
+    DpadStrafe,
+}
+
+impl Preset {
+    pub const ALL: [Preset; 3] = [Preset::Classic, Preset::HoldToStrafe, Preset::DpadStrafe];
+}
+
+/// Errors returned by [`Controls::from_bytes`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ControlsError {
+    /// The data was saved with another version of the format.
+    UnsupportedVersion { version: u8 },
+    /// The data is shorter than [`Controls::SERIALIZED_LEN`].
+    Truncated,
+    /// A byte doesn't name a button.
+    InvalidButton { offset: usize },
+}
+
+/// The binding of every [`Action`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Controls {
+    bindings: [Option<Binding>; Action::COUNT],
+}
+
+impl Controls {
+    /// Size of the output of [`Controls::to_bytes`]: a version byte, then a button byte and a
+    /// modifier byte for each action.
+    pub const SERIALIZED_LEN: usize = 1 + 2 * Action::COUNT;
+
+    pub const fn preset(preset: Preset) -> Self {
+        use ButtonId::*;
+
+        let bindings = match preset {
+            Preset::Classic => [
+                Some(Binding::new(Up)),
+                Some(Binding::new(Down)),
+                Some(Binding::new(Left)),
+                Some(Binding::new(Right)),
+                None,
+                None,
+                Some(Binding::new(B)),
+                Some(Binding::new(A)),
+                Some(Binding::new(Y)),
+            ],
+            Preset::HoldToStrafe => [
+                Some(Binding::new(Up)),
+                Some(Binding::new(Down)),
+                Some(Binding::without(Left, A)),
+                Some(Binding::without(Right, A)),
+                Some(Binding::with(Left, A)),
+                Some(Binding::with(Right, A)),
+                Some(Binding::new(X)),
+                Some(Binding::new(B)),
+                Some(Binding::new(Y)),
+            ],
+            Preset::DpadStrafe => [
+                Some(Binding::new(Up)),
+                Some(Binding::new(Down)),
+                Some(Binding::new(X)),
+                Some(Binding::new(Y)),
+                Some(Binding::new(Left)),
+                Some(Binding::new(Right)),
+                Some(Binding::without(B, A)),
+                Some(Binding::without(A, B)),
+                Some(Binding::with(B, A)),
+            ],
+        };
+        Self { bindings }
+    }
+
+    pub fn binding(&self, action: Action) -> Option<Binding> {
+        self.bindings[action as usize]
+    }
+
+    /// Binds `action` to `binding`, or unbinds it when `binding` is `None`.
+    pub fn bind(&mut self, action: Action, binding: Option<Binding>) {
+        self.bindings[action as usize] = binding;
+    }
+
+    /// Returns `true` while the binding of `action` is held down.
+    pub fn is_active(&self, action: Action, input: &Input) -> bool {
+        match self.binding(action) {
+            Some(binding) => input.is_down(binding.button) && binding.modifier_applies(input),
+            None => false,
+        }
+    }
+
+    /// Returns `true` if the binding of `action` went down on the last input update. A binding
+    /// with a held modifier is a chord, and goes down when the second of its two buttons does,
+    /// whichever one that is.
+    pub fn triggered(&self, action: Action, input: &Input) -> bool {
+        match self.binding(action) {
+            Some(Binding {
+                button,
+                modifier: Modifier::Held(modifier),
+            }) => {
+                (input.pressed(button) && input.is_down(modifier))
+                    || (input.pressed(modifier) && input.is_down(button))
+            }
+            Some(binding) => input.pressed(binding.button) && binding.modifier_applies(input),
+            None => false,
+        }
+    }
+
+    /// Returns the player movement asked for by the movement actions.
+    pub fn move_input(&self, input: &Input) -> MoveInput {
+        let axis = |positive: Action, negative: Action| {
+            self.is_active(positive, input) as i8 - self.is_active(negative, input) as i8
+        };
+        MoveInput {
+            forward: axis(Action::Forward, Action::Back),
+            strafe: axis(Action::StrafeRight, Action::StrafeLeft),
+            turn: axis(Action::TurnRight, Action::TurnLeft),
+        }
+    }
+
+    /// Serializes the bindings, to be saved with the settings.
+    pub fn to_bytes(&self) -> [u8; Controls::SERIALIZED_LEN] {
+        let mut bytes = [UNBOUND; Controls::SERIALIZED_LEN];
+        bytes[0] = VERSION;
+        for (binding, pair) in self.bindings.iter().zip(bytes[1..].chunks_exact_mut(2)) {
+            if let Some(binding) = binding {
+                pair[0] = binding.button.index() as u8;
+                pair[1] = match binding.modifier {
+                    Modifier::None => UNBOUND,
+                    Modifier::Held(button) => button.index() as u8,
+                    Modifier::Released(button) => button.index() as u8 | MODIFIER_RELEASED,
+                };
+            }
+        }
+        bytes
+    }
+
+    /// Reads bindings written by [`Controls::to_bytes`].
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ControlsError> {
+        if bytes.len() < Controls::SERIALIZED_LEN {
+            return Err(ControlsError::Truncated);
+        }
+        if bytes[0] != VERSION {
+            return Err(ControlsError::UnsupportedVersion { version: bytes[0] });
+        }
+
+        let button = |offset: usize, byte: u8| {
+            ButtonId::from_index(byte as usize).ok_or(ControlsError::InvalidButton { offset })
+        };
+        let mut bindings = [None; Action::COUNT];
+        for (index, binding) in bindings.iter_mut().enumerate() {
+            let offset = 1 + 2 * index;
+            let (button_byte, modifier_byte) = (bytes[offset], bytes[offset + 1]);
+            if button_byte == UNBOUND {
+                continue;
+            }
+            let modifier = match modifier_byte {
+                UNBOUND => Modifier::None,
+                byte if byte & MODIFIER_RELEASED != 0 => {
+                    Modifier::Released(button(offset + 1, byte & !MODIFIER_RELEASED)?)
+                }
+                byte => Modifier::Held(button(offset + 1, byte)?),
+            };
+            *binding = Some(Binding {
+                button: button(offset, button_byte)?,
+                modifier,
+            });
+        }
+        Ok(Self { bindings })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game_loop::TimeSource;
+    use crate::input::{ButtonSource, InputConfig};
+
+    /// Which buttons are held, indexed like [`ButtonId::ALL`].
+    struct Buttons([bool; ButtonId::COUNT]);
+
+    impl ButtonSource for Buttons {
+        fn is_pressed(&self, button: ButtonId) -> bool {
+            self.0[button.index()]
+        }
+    }
+
+    struct Clock;
+
+    impl TimeSource for Clock {
+        fn now_us(&self) -> u64 {
+            0
+        }
+    }
+
+    /// Feeds `steps` to an [`Input`] without debouncing, one update per step, and returns the
+    /// actions triggered by each update.
+    fn triggered(
+        controls: &Controls,
+        steps: &[&[ButtonId]],
+    ) -> std::vec::Vec<[bool; Action::COUNT]> {
+        let mut config = InputConfig::new();
+        config.debounce_ms = 0;
+        let mut input = Input::new(config);
+        steps
+            .iter()
+            .map(|held| {
+                let mut buttons = Buttons([false; ButtonId::COUNT]);
+                for button in held.iter() {
+                    buttons.0[button.index()] = true;
+                }
+                input.update(&buttons, &Clock);
+                Action::ALL.map(|action| controls.triggered(action, &input))
+            })
+            .collect()
+    }
+
+    fn only(action: Action) -> [bool; Action::COUNT] {
+        Action::ALL.map(|other| other == action)
+    }
+
+    #[test]
+    fn chords_trigger_whichever_button_goes_down_first() {
+        use ButtonId::*;
+
+        let controls = Controls::preset(Preset::DpadStrafe);
+        let none = [false; Action::COUNT];
+        assert_eq!(
+            triggered(&controls, &[&[A], &[A, B]]),
+            [only(Action::Fire), only(Action::Menu)]
+        );
+        assert_eq!(
+            triggered(&controls, &[&[B], &[A, B]]),
+            [only(Action::Use), only(Action::Menu)]
+        );
+        assert_eq!(
+            triggered(&controls, &[&[A, B], &[A, B]]),
+            [only(Action::Menu), none]
+        );
+    }
+
+    #[test]
+    fn released_modifiers_only_trigger_without_the_modifier() {
+        use ButtonId::*;
+
+        let controls = Controls::preset(Preset::HoldToStrafe);
+        let none = [false; Action::COUNT];
+        assert_eq!(
+            triggered(&controls, &[&[Left], &[], &[A], &[A, Left]]),
+            [only(Action::TurnLeft), none, none, only(Action::StrafeLeft)]
+        );
+    }
+
+    #[test]
+    fn bindings_round_trip_through_bytes() {
+        for preset in Preset::ALL {
+            let controls = Controls::preset(preset);
+            assert_eq!(Controls::from_bytes(&controls.to_bytes()), Ok(controls));
+        }
+    }
+}
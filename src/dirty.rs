@@ -0,0 +1,200 @@
+//! Tracks which parts of the screen changed, so only those are sent to the display.
+
+use heapless::Vec;
+
+/// A rectangle of screen pixels.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Rect {
+    pub x: u16,
+    pub y: u16,
+    pub width: u16,
+    pub height: u16,
+}
+
+impl Rect {
+    pub const fn new(x: u16, y: u16, width: u16, height: u16) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.width == 0 || self.height == 0
+    }
+
+    /// Returns the column just right of the rectangle, or `u16::MAX` if that's past it.
+    pub fn right(&self) -> u16 {
+        self.x.saturating_add(self.width)
+    }
+
+    /// Returns the row just below the rectangle, or `u16::MAX` if that's past it.
+    pub fn bottom(&self) -> u16 {
+        self.y.saturating_add(self.height)
+    }
+
+    /// Returns the number of pixels in the rectangle.
+    pub fn area(&self) -> u32 {
+        self.width as u32 * self.height as u32
+    }
+
+    /// Returns the smallest rectangle holding both rectangles.
+    pub fn union(&self, other: &Rect) -> Rect {
+        if self.is_empty() {
+            return *other;
+        }
+        if other.is_empty() {
+            return *self;
+        }
+        let x = self.x.min(other.x);
+        let y = self.y.min(other.y);
+        Rect::new(
+            x,
+            y,
+            self.right().max(other.right()) - x,
+            self.bottom().max(other.bottom()) - y,
+        )
+    }
+
+    /// Returns the pixels in both rectangles. Empty if they don't overlap.
+    pub fn intersection(&self, other: &Rect) -> Rect {
+        let x = self.x.max(other.x);
+        let y = self.y.max(other.y);
+        let right = self.right().min(other.right());
+        let bottom = self.bottom().min(other.bottom());
+        if right <= x || bottom <= y {
+            return Rect::default();
+        }
+        Rect::new(x, y, right - x, bottom - y)
+    }
+
+    /// Returns `true` if the rectangles overlap or are next to each other.
+    fn touches(&self, other: &Rect) -> bool {
+        self.x <= other.right()
+            && other.x <= self.right()
+            && self.y <= other.bottom()
+            && other.y <= self.bottom()
+    }
+}
+
+/// The parts of the screen that changed since the last update, as up to `N` rectangles that
+/// don't overlap.
+///
+/// Rectangles that overlap or are next to each other are merged into the rectangle holding
+/// both. Once there are `N` of them, a new rectangle is merged with the one it grows the least,
+/// so each update costs at most `N` display windows.
+pub struct DirtyRects<const N: usize> {
+    screen: Rect,
+    rects: Vec<Rect, N>,
+}
+
+impl<const N: usize> DirtyRects<N> {
+    /// Creates a tracker for a `width` by `height` screen, with nothing to update.
+    pub fn new(width: u16, height: u16) -> Self {
+        Self {
+            screen: Rect::new(0, 0, width, height),
+            rects: Vec::new(),
+        }
+    }
+
+    /// Marks `rect` as changed. Parts outside of the screen are ignored.
+    pub fn mark(&mut self, rect: Rect) {
+        let mut rect = rect.intersection(&self.screen);
+        if rect.is_empty() || N == 0 {
+            return;
+        }
+        loop {
+            let merge = match self.rects.iter().position(|other| other.touches(&rect)) {
+                Some(index) => index,
+                None if self.rects.is_full() => self.cheapest_merge(&rect),
+                None => break,
+            };
+            rect = rect.union(&self.rects.swap_remove(merge));
+        }
+        // Can't fail: the loop only ends when there's room left.
+        let _ = self.rects.push(rect);
+    }
+
+    /// Marks the whole screen as changed.
+    pub fn mark_all(&mut self) {
+        self.rects.clear();
+        self.mark(self.screen);
+    }
+
+    /// Returns `true` if nothing changed.
+    pub fn is_clean(&self) -> bool {
+        self.rects.is_empty()
+    }
+
+    /// Returns the changed rectangles.
+    pub fn rects(&self) -> &[Rect] {
+        &self.rects
+    }
+
+    /// Returns the number of pixels to send to update the changed rectangles.
+    pub fn pixels(&self) -> u32 {
+        self.rects.iter().map(Rect::area).sum()
+    }
+
+    /// Forgets every change, once the display is up to date.
+    pub fn clear(&mut self) {
+        self.rects.clear();
+    }
+
+    /// Returns the index of the rectangle whose union with `rect` adds the fewest pixels.
+    fn cheapest_merge(&self, rect: &Rect) -> usize {
+        let cost = |other: &Rect| other.union(rect).area() - other.area();
+        (0..self.rects.len())
+            .min_by_key(|&index| cost(&self.rects[index]))
+            .unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ignores_rects_off_the_screen() {
+        let mut dirty = DirtyRects::<4>::new(240, 240);
+        dirty.mark(Rect::new(65000, 0, 1000, 1));
+        dirty.mark(Rect::new(0, 240, 10, 10));
+        assert!(dirty.is_clean());
+
+        dirty.mark(Rect::new(230, 65000, 20, 1000));
+        dirty.mark(Rect::new(230, 235, u16::MAX, u16::MAX));
+        assert_eq!(dirty.rects(), [Rect::new(230, 235, 10, 5)]);
+    }
+
+    #[test]
+    fn merges_touching_rects() {
+        let mut dirty = DirtyRects::<4>::new(240, 240);
+        dirty.mark(Rect::new(0, 0, 10, 10));
+        dirty.mark(Rect::new(100, 100, 10, 10));
+        assert_eq!(dirty.rects().len(), 2);
+        assert_eq!(dirty.pixels(), 200);
+
+        // Next to the first one.
+        dirty.mark(Rect::new(10, 0, 5, 5));
+        assert_eq!(dirty.rects().len(), 2);
+        assert!(dirty.rects().contains(&Rect::new(0, 0, 15, 10)));
+    }
+
+    #[test]
+    fn merges_the_cheapest_rect_once_full() {
+        let mut dirty = DirtyRects::<2>::new(240, 240);
+        dirty.mark(Rect::new(0, 0, 10, 10));
+        dirty.mark(Rect::new(200, 200, 10, 10));
+        dirty.mark(Rect::new(20, 0, 10, 10));
+        assert_eq!(dirty.rects().len(), 2);
+        assert!(dirty.rects().contains(&Rect::new(0, 0, 30, 10)));
+        assert!(dirty.rects().contains(&Rect::new(200, 200, 10, 10)));
+
+        dirty.mark_all();
+        assert_eq!(dirty.rects(), [Rect::new(0, 0, 240, 240)]);
+        dirty.clear();
+        assert!(dirty.is_clean());
+    }
+}
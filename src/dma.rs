@@ -0,0 +1,146 @@
+//! Background transfers of frame buffers to the display.
+
+use core::sync::atomic::{compiler_fence, Ordering};
+
+/// Hardware that streams a buffer to the display without the CPU, such as a DMA channel feeding
+/// an SPI peripheral.
+pub trait DmaChannel {
+    /// Starts sending `len` pixels read from `source`, and returns straight away.
+    ///
+    /// # Safety
+    ///
+    /// `source` must stay valid, and must not be written, until [`DmaChannel::is_done`] returns
+    /// `true`.
+    unsafe fn start(&mut self, source: *const u16, len: usize);
+
+    /// Returns `true` once every pixel has been sent and the channel can be used again.
+    fn is_done(&mut self) -> bool;
+}
+
+/// A frame buffer being sent in the background.
+///
+/// The transfer owns the buffer and the channel until it's done, so nothing can write to the
+/// buffer mid-transfer, or start another transfer on the channel. [`Transfer::wait`] hands them
+/// back. Dropping a transfer waits for it to finish.
+pub struct Transfer<C: DmaChannel> {
+    /// Only `None` once [`Transfer::wait`] has handed them back.
+    parts: Option<(C, &'static mut [u16])>,
+    done: bool,
+}
+
+impl<C: DmaChannel> Transfer<C> {
+    /// Starts sending the first `len` pixels of `buffer` on `channel`. The transfer owns the
+    /// whole buffer anyway, so it comes back whole.
+    ///
+    /// Panics if `len` is longer than `buffer`.
+    pub fn start(mut channel: C, buffer: &'static mut [u16], len: usize) -> Self {
+        assert!(len <= buffer.len());
+        // Make sure every write to the buffer happens before the hardware starts reading it.
+        compiler_fence(Ordering::Release);
+        // The buffer can't be touched until the transfer is done, since `Transfer` owns it.
+        unsafe { channel.start(buffer.as_ptr(), len) };
+        Self {
+            parts: Some((channel, buffer)),
+            done: false,
+        }
+    }
+
+    /// Returns `true` once the whole buffer has been sent, without blocking.
+    pub fn is_done(&mut self) -> bool {
+        if let Some((channel, _)) = &mut self.parts {
+            if !self.done && channel.is_done() {
+                self.done = true;
+                compiler_fence(Ordering::Acquire);
+            }
+        }
+        self.done
+    }
+
+    /// Blocks until the whole buffer has been sent, and returns the channel and the buffer.
+    pub fn wait(mut self) -> (C, &'static mut [u16]) {
+        while !self.is_done() {}
+        // Can't fail: `parts` is only taken here, and `wait` consumes the transfer.
+        self.parts.take().unwrap()
+    }
+}
+
+impl<C: DmaChannel> Drop for Transfer<C> {
+    fn drop(&mut self) {
+        while !self.is_done() {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::cell::Cell;
+
+    use super::*;
+    use crate::testing::buffer;
+
+    /// A channel that reports done after being polled `busy_polls` times.
+    struct FakeChannel<'a> {
+        busy_polls: &'a Cell<u32>,
+        started: Option<(*const u16, usize)>,
+    }
+
+    impl<'a> FakeChannel<'a> {
+        fn new(busy_polls: &'a Cell<u32>) -> Self {
+            Self {
+                busy_polls,
+                started: None,
+            }
+        }
+    }
+
+    impl DmaChannel for FakeChannel<'_> {
+        unsafe fn start(&mut self, source: *const u16, len: usize) {
+            assert!(self.started.is_none(), "started twice");
+            self.started = Some((source, len));
+        }
+
+        fn is_done(&mut self) -> bool {
+            assert!(self.started.is_some(), "polled before starting");
+            match self.busy_polls.get() {
+                0 => true,
+                polls => {
+                    self.busy_polls.set(polls - 1);
+                    false
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn hands_the_buffer_back_only_once_sent() {
+        let busy_polls = Cell::new(3);
+        let buffer = buffer(16);
+        let address = buffer.as_ptr();
+        let mut transfer = Transfer::start(FakeChannel::new(&busy_polls), buffer, 10);
+        assert!(!transfer.is_done());
+        assert_eq!(busy_polls.get(), 2);
+
+        let (channel, buffer) = transfer.wait();
+        assert_eq!(busy_polls.get(), 0);
+        assert_eq!(channel.started, Some((address, 10)));
+        assert_eq!(buffer.as_ptr(), address);
+        assert_eq!(buffer.len(), 16);
+    }
+
+    #[test]
+    fn stays_done_without_polling_again() {
+        let busy_polls = Cell::new(0);
+        let mut transfer = Transfer::start(FakeChannel::new(&busy_polls), buffer(4), 4);
+        assert!(transfer.is_done());
+        busy_polls.set(5);
+        assert!(transfer.is_done());
+        let _ = transfer.wait();
+        assert_eq!(busy_polls.get(), 5);
+    }
+
+    #[test]
+    fn dropping_waits_for_the_transfer() {
+        let busy_polls = Cell::new(10);
+        drop(Transfer::start(FakeChannel::new(&busy_polls), buffer(4), 4));
+        assert_eq!(busy_polls.get(), 0);
+    }
+}
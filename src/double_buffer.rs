@@ -0,0 +1,266 @@
+//! Double buffering, so a frame can be drawn while the previous one is sent to the display.
+
+use core::ops::Range;
+
+use crate::dirty::Rect;
+use crate::dma::{DmaChannel, Transfer};
+
+/// A display that takes pixels from a [`DmaChannel`].
+pub trait FrameSink {
+    type Error;
+
+    /// Gets the display ready to take the pixels of `rect`, row by row from its top left
+    /// corner.
+    fn begin_region(&mut self, rect: Rect) -> Result<(), Self::Error>;
+}
+
+/// Returns how many rows of a `width` by `height` screen a buffer of at most `max_len` pixels
+/// holds, picking a number that divides `height` so every strip of the screen is the same size.
+/// Returns zero if not even a row fits.
+// `is_multiple_of` needs a newer compiler than the firmware is built with.
+#[allow(clippy::manual_is_multiple_of)]
+pub const fn strip_rows(width: u16, height: u16, max_len: usize) -> u16 {
+    let mut rows = height;
+    while rows > 0 {
+        if height % rows == 0 && rows as usize * width as usize <= max_len {
+            return rows;
+        }
+        rows -= 1;
+    }
+    0
+}
+
+/// Returns the length, in pixels, of each of two buffers that fit in `budget` bytes for a
+/// `width` by `height` screen: a whole frame if two fit, a strip of the screen otherwise.
+pub const fn buffer_len(width: u16, height: u16, budget: usize) -> usize {
+    let max_len = budget / 2 / core::mem::size_of::<u16>();
+    strip_rows(width, height, max_len) as usize * width as usize
+}
+
+/// Two frame buffers that take turns: one is drawn into while the other one is sent.
+///
+/// Buffers too small for a whole frame hold strips of rows, and [`DoubleBuffer::draw`] then
+/// draws and sends a frame one strip at a time. The display is owned by the double buffer, as
+/// it can't be sent anything else while a buffer is being sent.
+pub struct DoubleBuffer<S, C: DmaChannel> {
+    sink: S,
+    width: u16,
+    height: u16,
+    back: &'static mut [u16],
+    /// The front buffer while it's being sent.
+    sending: Option<Transfer<C>>,
+    /// The channel and the front buffer when nothing is being sent.
+    idle: Option<(C, &'static mut [u16])>,
+}
+
+impl<S: FrameSink, C: DmaChannel> DoubleBuffer<S, C> {
+    /// Creates a double buffer for a `width` by `height` screen, using as many rows of `first`
+    /// and `second` as evenly divide the screen.
+    ///
+    /// Panics if the buffers don't hold a row.
+    pub fn new(
+        sink: S,
+        channel: C,
+        first: &'static mut [u16],
+        second: &'static mut [u16],
+        width: u16,
+        height: u16,
+    ) -> Self {
+        let rows = strip_rows(width, height, first.len().min(second.len()));
+        assert!(rows > 0, "frame buffers are smaller than a row");
+        let len = rows as usize * width as usize;
+        Self {
+            sink,
+            width,
+            height,
+            back: &mut first[..len],
+            sending: None,
+            idle: Some((channel, &mut second[..len])),
+        }
+    }
+
+    /// Draws a whole frame with `render` and starts sending it, one strip at a time.
+    ///
+    /// `render` is given the rows to draw, and a buffer holding just those rows, `width` pixels
+    /// each. It draws into one buffer while the other one is sent. Returns once the last strip
+    /// is being sent, so whatever runs next overlaps with it.
+    pub fn draw(&mut self, mut render: impl FnMut(Range<u16>, &mut [u16])) -> Result<(), S::Error> {
+        let screen = Rect::new(0, 0, self.width, self.height);
+        self.draw_rects(&[screen], |rect, buffer| {
+            render(rect.y..rect.bottom(), buffer)
+        })
+    }
+
+    /// Like [`DoubleBuffer::draw`], but only draws and sends `rects`, such as the ones tracked
+    /// by [`crate::dirty::DirtyRects`]. Parts of them outside of the screen are skipped.
+    ///
+    /// `render` is given a rectangle to draw, and a buffer holding just its pixels, row by row.
+    /// Rectangles are cut into strips of as many rows as fit in a buffer.
+    pub fn draw_rects(
+        &mut self,
+        rects: &[Rect],
+        mut render: impl FnMut(Rect, &mut [u16]),
+    ) -> Result<(), S::Error> {
+        let screen = Rect::new(0, 0, self.width, self.height);
+        for rect in rects.iter().map(|rect| rect.intersection(&screen)) {
+            if rect.is_empty() {
+                continue;
+            }
+            let rows = (self.back.len() / rect.width as usize) as u16;
+            let mut top = rect.y;
+            while top < rect.bottom() {
+                let strip = Rect::new(rect.x, top, rect.width, rows.min(rect.bottom() - top));
+                let len = strip.area() as usize;
+                render(strip, &mut self.back[..len]);
+
+                let (channel, front) = self.finish();
+                if let Err(error) = self.sink.begin_region(strip) {
+                    self.idle = Some((channel, front));
+                    return Err(error);
+                }
+                let back = core::mem::replace(&mut self.back, front);
+                self.sending = Some(Transfer::start(channel, back, len));
+                top = strip.bottom();
+            }
+        }
+        Ok(())
+    }
+
+    /// Waits for the front buffer to be sent, and returns it with the channel.
+    fn finish(&mut self) -> (C, &'static mut [u16]) {
+        match self.sending.take() {
+            Some(transfer) => transfer.wait(),
+            // Can't fail: the channel and front buffer are either being sent or idle.
+            None => self.idle.take().unwrap(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::cell::RefCell;
+    use std::format;
+    use std::string::String;
+    use std::vec::Vec;
+
+    use super::*;
+    use crate::testing::buffer;
+
+    /// Logs what the display and the channel are asked to do.
+    type Log<'a> = &'a RefCell<Vec<String>>;
+
+    struct FakeSink<'a>(Log<'a>);
+
+    impl FrameSink for FakeSink<'_> {
+        type Error = ();
+
+        fn begin_region(&mut self, rect: Rect) -> Result<(), ()> {
+            let Rect {
+                x,
+                y,
+                width,
+                height,
+            } = rect;
+            let window = format!("window {} {} {}x{}", x, y, width, height);
+            self.0.borrow_mut().push(window);
+            Ok(())
+        }
+    }
+
+    /// Logs the first pixel and the length of every buffer it's asked to send.
+    struct FakeChannel<'a>(Log<'a>);
+
+    impl DmaChannel for FakeChannel<'_> {
+        unsafe fn start(&mut self, source: *const u16, len: usize) {
+            let first = *source;
+            self.0.borrow_mut().push(format!("send {} {}", first, len));
+        }
+
+        fn is_done(&mut self) -> bool {
+            true
+        }
+    }
+
+    #[test]
+    fn strips_evenly_divide_the_screen() {
+        assert_eq!(strip_rows(240, 240, 240 * 240), 240);
+        assert_eq!(strip_rows(240, 240, 240 * 136), 120);
+        assert_eq!(strip_rows(240, 240, 240 * 7), 6);
+        assert_eq!(strip_rows(240, 240, 239), 0);
+        assert_eq!(buffer_len(240, 240, 128 * 1024), 240 * 120);
+        assert_eq!(buffer_len(240, 240, 2 * 2 * 240 * 240), 240 * 240);
+    }
+
+    #[test]
+    fn draws_and_sends_a_frame_one_strip_at_a_time() {
+        let log = RefCell::new(Vec::new());
+        let mut frame = DoubleBuffer::new(
+            FakeSink(&log),
+            FakeChannel(&log),
+            buffer(4 * 3),
+            buffer(4 * 2),
+            4,
+            6,
+        );
+        frame
+            .draw(|rows, buffer| {
+                log.borrow_mut().push(format!("draw {:?}", rows));
+                assert_eq!(buffer.len(), 4 * 2);
+                buffer.fill(rows.start);
+            })
+            .unwrap();
+        assert_eq!(
+            *log.borrow(),
+            [
+                "draw 0..2",
+                "window 0 0 4x2",
+                "send 0 8",
+                "draw 2..4",
+                "window 0 2 4x2",
+                "send 2 8",
+                "draw 4..6",
+                "window 0 4 4x2",
+                "send 4 8",
+            ]
+        );
+    }
+
+    #[test]
+    fn only_sends_the_rects_asked_for() {
+        let log = RefCell::new(Vec::new());
+        let mut frame = DoubleBuffer::new(
+            FakeSink(&log),
+            FakeChannel(&log),
+            buffer(4 * 2),
+            buffer(4 * 2),
+            4,
+            6,
+        );
+        let rects = [
+            Rect::new(1, 1, 2, 5),
+            Rect::new(0, 0, 0, 3),
+            Rect::new(3, 5, 100, 100),
+        ];
+        frame
+            .draw_rects(&rects, |rect, buffer| {
+                log.borrow_mut().push(format!("draw {:?}", rect));
+                buffer.fill(rect.y);
+            })
+            .unwrap();
+        let draw = |x, y, width, height| format!("draw {:?}", Rect::new(x, y, width, height));
+        assert_eq!(
+            *log.borrow(),
+            [
+                draw(1, 1, 2, 4),
+                "window 1 1 2x4".into(),
+                "send 1 8".into(),
+                draw(1, 5, 2, 1),
+                "window 1 5 2x1".into(),
+                "send 5 2".into(),
+                draw(3, 5, 1, 1),
+                "window 3 5 1x1".into(),
+                "send 5 1".into(),
+            ]
+        );
+    }
+}
@@ -0,0 +1,71 @@
+//! Runs work on the RP2040's second core.
+
+use core::ptr::addr_of_mut;
+
+use pimoroni_picosystem::hal::{
+    multicore::{Multicore, Stack},
+    pac,
+    sio::SioFifo,
+    Sio,
+};
+
+/// Words in the stack of core 1, so 4 KB. Jobs cast rays and draw columns, which only keeps a
+/// few hundred bytes of locals, and the rest is headroom.
+const CORE1_STACK_WORDS: usize = 1024;
+/// Size of the stack of core 1 in bytes, counted in the game's RAM budget.
+pub const CORE1_STACK_LEN: usize = CORE1_STACK_WORDS * 4;
+
+static mut CORE1_STACK: Stack<CORE1_STACK_WORDS> = Stack::new();
+
+/// Sent back by core 1 when it's done with a job.
+const JOB_DONE: u32 = 1;
+
+type Job<'a> = &'a mut (dyn FnMut() + Send + 'a);
+
+/// Core 1, waiting for jobs from core 0.
+///
+/// Jobs are passed by address through the SIO FIFO, and core 1 answers once it's done with
+/// them, so they can borrow from the stack of core 0, like scoped threads.
+pub struct Core1 {
+    fifo: SioFifo,
+}
+
+impl Core1 {
+    /// Starts core 1. Can only be called once.
+    pub fn start(psm: &mut pac::PSM, ppb: &mut pac::PPB, mut fifo: SioFifo) -> Self {
+        let mut multicore = Multicore::new(psm, ppb, &mut fifo);
+        let cores = multicore.cores();
+        // Only borrowed here, and core 1 never returns, so this is the only reference to it.
+        let stack = unsafe { &mut (*addr_of_mut!(CORE1_STACK)).mem };
+        cores[1].spawn(stack, run_jobs).unwrap();
+        Self { fifo }
+    }
+
+    /// Runs `first` on this core and `second` on core 1 at the same time, and returns once both
+    /// are done.
+    pub fn join<A: FnOnce(), B: FnOnce() + Send>(&mut self, first: A, second: B) {
+        let mut second = Some(second);
+        let mut job = || {
+            if let Some(second) = second.take() {
+                second();
+            }
+        };
+        let mut job: Job = &mut job;
+        // `job` outlives its use by core 1, since this only returns once core 1 is done.
+        self.fifo.write_blocking(&mut job as *mut Job as u32);
+        first();
+        while self.fifo.read_blocking() != JOB_DONE {}
+    }
+}
+
+fn run_jobs() -> ! {
+    // Each core has its own end of the FIFO.
+    let pac = unsafe { pac::Peripherals::steal() };
+    let mut fifo = Sio::new(pac.SIO).fifo;
+    loop {
+        let job = fifo.read_blocking() as *mut Job;
+        // Core 0 keeps the job alive until it hears back.
+        unsafe { (*job)() };
+        fifo.write_blocking(JOB_DONE);
+    }
+}
@@ -0,0 +1,154 @@
+//! Fixed timestep game loop, so the game runs at the same speed however long frames take to
+//! render.
+
+#[cfg(all(target_arch = "arm", target_os = "none"))]
+use pimoroni_picosystem::hal::Timer;
+
+use crate::raycaster::Fixed;
+
+/// Simulation steps run at most per [`GameLoop::advance`] by default. Time beyond that is
+/// dropped, so a slow frame makes the game slow down for a moment instead of spending ever
+/// longer catching up.
+const DEFAULT_MAX_STEPS: u32 = 5;
+
+/// A monotonic clock, counting microseconds.
+pub trait TimeSource {
+    fn now_us(&self) -> u64;
+}
+
+#[cfg(all(target_arch = "arm", target_os = "none"))]
+impl TimeSource for Timer {
+    fn now_us(&self) -> u64 {
+        self.get_counter().ticks()
+    }
+}
+
+impl<T: TimeSource + ?Sized> TimeSource for &T {
+    fn now_us(&self) -> u64 {
+        (**self).now_us()
+    }
+}
+
+/// Accumulates the real time elapsed between frames, and hands it out in fixed size simulation
+/// steps.
+///
+/// Each iteration of the main loop calls [`GameLoop::advance`], runs the simulation that many
+/// times, and then renders once. Time left over that doesn't make a whole step is kept for the
+/// next frame, and [`GameLoop::alpha`] tells how far into the next step it reaches, to
+/// interpolate between the last two simulation states when rendering.
+pub struct GameLoop<T> {
+    clock: T,
+    /// Length of a simulation step, in microseconds.
+    step_us: u64,
+    /// Most steps returned by a single call to [`GameLoop::advance`].
+    pub max_steps: u32,
+    last_us: u64,
+    accumulator_us: u64,
+}
+
+impl<T: TimeSource> GameLoop<T> {
+    /// Creates a loop running `steps_per_second` simulation steps per second, starting now.
+    pub fn new(clock: T, steps_per_second: u32) -> Self {
+        let last_us = clock.now_us();
+        Self {
+            clock,
+            step_us: 1_000_000 / steps_per_second.max(1) as u64,
+            max_steps: DEFAULT_MAX_STEPS,
+            last_us,
+            accumulator_us: 0,
+        }
+    }
+
+    /// Returns the length of a simulation step, in microseconds.
+    pub fn step_us(&self) -> u64 {
+        self.step_us
+    }
+
+    /// Adds the time elapsed since the last call, and returns how many simulation steps to run
+    /// before rendering the next frame.
+    pub fn advance(&mut self) -> u32 {
+        let now_us = self.clock.now_us();
+        self.accumulator_us += now_us.saturating_sub(self.last_us);
+        self.last_us = now_us;
+
+        let steps = self.accumulator_us / self.step_us;
+        if steps > self.max_steps as u64 {
+            self.accumulator_us %= self.step_us;
+            return self.max_steps;
+        }
+        self.accumulator_us -= steps * self.step_us;
+        steps as u32
+    }
+
+    /// Returns how far the time left over by [`GameLoop::advance`] reaches into the next step,
+    /// from zero up to (but not including) one.
+    pub fn alpha(&self) -> Fixed {
+        Fixed::from_bits((Fixed::ONE.to_bits() as u64 * self.accumulator_us / self.step_us) as i32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::cell::Cell;
+
+    use super::*;
+
+    /// A clock that only moves when told to.
+    struct FakeClock(Cell<u64>);
+
+    impl FakeClock {
+        fn tick(&self, us: u64) {
+            self.0.set(self.0.get() + us);
+        }
+    }
+
+    impl TimeSource for FakeClock {
+        fn now_us(&self) -> u64 {
+            self.0.get()
+        }
+    }
+
+    #[test]
+    fn hands_out_whole_steps_and_keeps_the_rest() {
+        let clock = FakeClock(Cell::new(1_000));
+        let mut game_loop = GameLoop::new(&clock, 50);
+        assert_eq!(game_loop.step_us(), 20_000);
+        assert_eq!(game_loop.advance(), 0);
+        assert_eq!(game_loop.alpha(), Fixed::ZERO);
+
+        clock.tick(10_000);
+        assert_eq!(game_loop.advance(), 0);
+        assert_eq!(game_loop.alpha(), Fixed::HALF);
+
+        clock.tick(45_000);
+        assert_eq!(game_loop.advance(), 2);
+        assert_eq!(game_loop.alpha(), Fixed::from_f32(0.75));
+
+        clock.tick(5_000);
+        assert_eq!(game_loop.advance(), 1);
+        assert_eq!(game_loop.alpha(), Fixed::ZERO);
+    }
+
+    #[test]
+    fn drops_time_past_max_steps() {
+        let clock = FakeClock(Cell::new(0));
+        let mut game_loop = GameLoop::new(&clock, 50);
+        game_loop.max_steps = 3;
+        clock.tick(1_000_000 + 5_000);
+        assert_eq!(game_loop.advance(), 3);
+        assert_eq!(game_loop.alpha(), Fixed::from_f32(0.25));
+
+        clock.tick(15_000);
+        assert_eq!(game_loop.advance(), 1);
+    }
+
+    #[test]
+    fn ignores_clocks_going_backwards() {
+        let clock = FakeClock(Cell::new(50_000));
+        let mut game_loop = GameLoop::new(&clock, 50);
+        clock.0.set(10_000);
+        assert_eq!(game_loop.advance(), 0);
+        clock.tick(20_000);
+        assert_eq!(game_loop.advance(), 1);
+    }
+}
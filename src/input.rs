@@ -0,0 +1,312 @@
+//! Button state tracking: debouncing, press and release edges, hold times and auto-repeat.
+
+use crate::game_loop::TimeSource;
+
+/// The PicoSystem's buttons: the four directions of the D-pad and the four face buttons.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ButtonId {
+    Up,
+    Down,
+    Left,
+    Right,
+    A,
+    B,
+    X,
+    Y,
+}
+
+impl ButtonId {
+    pub const COUNT: usize = 8;
+
+    /// Every button, in declaration order, for code that loops over them.
+    pub const ALL: [ButtonId; ButtonId::COUNT] = [
+        ButtonId::Up,
+        ButtonId::Down,
+        ButtonId::Left,
+        ButtonId::Right,
+        ButtonId::A,
+        ButtonId::B,
+        ButtonId::X,
+        ButtonId::Y,
+    ];
+
+    /// Returns the position of the button in [`ButtonId::ALL`].
+    pub const fn index(self) -> usize {
+        self as usize
+    }
+
+    /// Returns the button at `index` in [`ButtonId::ALL`], if there's one.
+    pub const fn from_index(index: usize) -> Option<ButtonId> {
+        if index < ButtonId::COUNT {
+            Some(ButtonId::ALL[index])
+        } else {
+            None
+        }
+    }
+}
+
+/// Raw button readings, such as GPIO pins.
+pub trait ButtonSource {
+    /// Returns `true` while `button` is physically held down.
+    fn is_pressed(&self, button: ButtonId) -> bool;
+}
+
+/// Timings used by [`Input`], in milliseconds.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InputConfig {
+    /// How long a reading must stay the same before it's trusted. Zero turns debouncing off.
+    pub debounce_ms: u32,
+    /// How long a button is held before it starts repeating.
+    pub repeat_delay_ms: u32,
+    /// Time between repeats once a button is repeating. Zero turns repeating off.
+    pub repeat_interval_ms: u32,
+}
+
+impl InputConfig {
+    pub const fn new() -> Self {
+        Self {
+            debounce_ms: 10,
+            repeat_delay_ms: 400,
+            repeat_interval_ms: 100,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+struct ButtonState {
+    /// Last raw reading, and when it last changed.
+    raw: bool,
+    raw_since_us: u64,
+    /// Debounced state, and when it last changed.
+    down: bool,
+    down_since_us: u64,
+    pressed: bool,
+    released: bool,
+    repeated: bool,
+    next_repeat_us: u64,
+}
+
+/// The debounced state of every button, sampled once per frame with [`Input::update`].
+///
+/// Edge events ([`Input::pressed`], [`Input::released`] and [`Input::repeated`]) stay set until
+/// the next update, so everything that runs between two updates sees them.
+pub struct Input {
+    pub config: InputConfig,
+    buttons: [ButtonState; ButtonId::COUNT],
+    now_us: u64,
+}
+
+impl Input {
+    pub fn new(config: InputConfig) -> Self {
+        Self {
+            config,
+            buttons: [ButtonState::default(); ButtonId::COUNT],
+            now_us: 0,
+        }
+    }
+
+    /// Reads every button from `source`, and updates their states as of the time on `clock`.
+    pub fn update(&mut self, source: &impl ButtonSource, clock: &impl TimeSource) {
+        let now_us = clock.now_us();
+        let debounce_us = self.config.debounce_ms as u64 * 1000;
+        let repeat_delay_us = self.config.repeat_delay_ms as u64 * 1000;
+        let repeat_interval_us = self.config.repeat_interval_ms as u64 * 1000;
+        self.now_us = now_us;
+
+        for (&id, state) in ButtonId::ALL.iter().zip(self.buttons.iter_mut()) {
+            state.pressed = false;
+            state.released = false;
+            state.repeated = false;
+
+            let raw = source.is_pressed(id);
+            if raw != state.raw {
+                state.raw = raw;
+                state.raw_since_us = now_us;
+            }
+
+            if state.raw != state.down && now_us.saturating_sub(state.raw_since_us) >= debounce_us {
+                state.down = state.raw;
+                state.down_since_us = now_us;
+                if state.down {
+                    state.pressed = true;
+                    state.repeated = true;
+                    state.next_repeat_us = now_us + repeat_delay_us;
+                } else {
+                    state.released = true;
+                }
+            } else if state.down && repeat_interval_us > 0 && now_us >= state.next_repeat_us {
+                state.repeated = true;
+                state.next_repeat_us += repeat_interval_us;
+                // Skip repeats missed by slow frames instead of firing them all at once.
+                if state.next_repeat_us <= now_us {
+                    state.next_repeat_us = now_us + repeat_interval_us;
+                }
+            }
+        }
+    }
+
+    /// Returns `true` while `button` is held down.
+    pub fn is_down(&self, button: ButtonId) -> bool {
+        self.buttons[button.index()].down
+    }
+
+    /// Returns `true` if `button` went down on the last update.
+    pub fn pressed(&self, button: ButtonId) -> bool {
+        self.buttons[button.index()].pressed
+    }
+
+    /// Returns `true` if `button` went up on the last update.
+    pub fn released(&self, button: ButtonId) -> bool {
+        self.buttons[button.index()].released
+    }
+
+    /// Returns `true` if `button` went down on the last update, or has been held long enough to
+    /// repeat, like a key on a keyboard. Meant for menus and other step-by-step input.
+    pub fn repeated(&self, button: ButtonId) -> bool {
+        self.buttons[button.index()].repeated
+    }
+
+    /// Returns how long `button` has been held down, as of the last update, in milliseconds.
+    /// Returns `None` if it's up.
+    pub fn held_ms(&self, button: ButtonId) -> Option<u32> {
+        let state = &self.buttons[button.index()];
+        if !state.down {
+            return None;
+        }
+        Some((self.now_us.saturating_sub(state.down_since_us) / 1000) as u32)
+    }
+
+    /// Returns `true` if `button` has been held down for at least `ms` milliseconds.
+    pub fn held_for(&self, button: ButtonId, ms: u32) -> bool {
+        matches!(self.held_ms(button), Some(held) if held >= ms)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::cell::Cell;
+
+    use super::*;
+
+    /// Button A, and a clock, both set by hand.
+    struct Fake {
+        a: Cell<bool>,
+        now_us: Cell<u64>,
+    }
+
+    impl ButtonSource for Fake {
+        fn is_pressed(&self, button: ButtonId) -> bool {
+            button == ButtonId::A && self.a.get()
+        }
+    }
+
+    impl TimeSource for Fake {
+        fn now_us(&self) -> u64 {
+            self.now_us.get()
+        }
+    }
+
+    /// Updates `input` at `ms` milliseconds, with button A held or not.
+    fn update(input: &mut Input, ms: u64, a: bool) {
+        let fake = Fake {
+            a: Cell::new(a),
+            now_us: Cell::new(ms * 1000),
+        };
+        input.update(&fake, &fake);
+    }
+
+    fn without_debounce() -> Input {
+        let mut config = InputConfig::new();
+        config.debounce_ms = 0;
+        Input::new(config)
+    }
+
+    #[test]
+    fn bounces_shorter_than_debounce_are_ignored() {
+        let mut input = Input::new(InputConfig::new());
+        for (ms, a) in [(0, true), (5, false), (8, true), (12, false), (30, false)] {
+            update(&mut input, ms, a);
+            assert!(!input.pressed(ButtonId::A) && !input.is_down(ButtonId::A));
+        }
+
+        update(&mut input, 40, true);
+        update(&mut input, 49, true);
+        assert!(!input.pressed(ButtonId::A));
+        update(&mut input, 50, true);
+        assert!(input.pressed(ButtonId::A));
+
+        update(&mut input, 60, false);
+        update(&mut input, 65, true);
+        update(&mut input, 80, true);
+        assert!(input.is_down(ButtonId::A));
+        assert!(!input.released(ButtonId::A));
+    }
+
+    #[test]
+    fn edges_last_one_update() {
+        let mut input = Input::new(InputConfig::new());
+        update(&mut input, 0, true);
+        update(&mut input, 10, true);
+        assert!(input.pressed(ButtonId::A));
+        update(&mut input, 11, true);
+        assert!(!input.pressed(ButtonId::A));
+        assert!(input.is_down(ButtonId::A));
+
+        update(&mut input, 20, false);
+        update(&mut input, 30, false);
+        assert!(input.released(ButtonId::A));
+        assert!(!input.is_down(ButtonId::A));
+        update(&mut input, 31, false);
+        assert!(!input.released(ButtonId::A));
+        assert!(!input.pressed(ButtonId::B));
+    }
+
+    #[test]
+    fn repeats_after_the_delay_and_then_every_interval() {
+        let mut input = without_debounce();
+        let mut repeats = std::vec::Vec::new();
+        for ms in (0..=700).step_by(50) {
+            update(&mut input, ms, true);
+            if input.repeated(ButtonId::A) {
+                repeats.push(ms);
+            }
+        }
+        assert_eq!(repeats, [0, 400, 500, 600, 700]);
+    }
+
+    #[test]
+    fn slow_frames_fire_one_missed_repeat() {
+        let mut input = without_debounce();
+        update(&mut input, 0, true);
+        update(&mut input, 400, true);
+        assert!(input.repeated(ButtonId::A));
+
+        // Three repeats were due in the meantime.
+        update(&mut input, 750, true);
+        assert!(input.repeated(ButtonId::A));
+        update(&mut input, 751, true);
+        assert!(!input.repeated(ButtonId::A));
+        update(&mut input, 849, true);
+        assert!(!input.repeated(ButtonId::A));
+        update(&mut input, 850, true);
+        assert!(input.repeated(ButtonId::A));
+    }
+
+    #[test]
+    fn hold_time_is_only_known_while_held() {
+        let mut input = without_debounce();
+        update(&mut input, 100, false);
+        assert_eq!(input.held_ms(ButtonId::A), None);
+        assert!(!input.held_for(ButtonId::A, 0));
+
+        update(&mut input, 200, true);
+        assert_eq!(input.held_ms(ButtonId::A), Some(0));
+        update(&mut input, 450, true);
+        assert_eq!(input.held_ms(ButtonId::A), Some(250));
+        assert!(input.held_for(ButtonId::A, 250));
+        assert!(!input.held_for(ButtonId::A, 251));
+
+        update(&mut input, 500, false);
+        assert_eq!(input.held_ms(ButtonId::A), None);
+    }
+}
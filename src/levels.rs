@@ -0,0 +1,57 @@
+//! Built-in levels, stored in flash as text maps (see [`crate::raycaster::parse_map`]), and the
+//! flash slot for a level written separately as a binary [`Level`](crate::raycaster::Level).
+
+use crate::raycaster::{Angle, Fixed, Spawn, Sprite};
+use crate::textures;
+
+/// Number of cells in the largest built-in level.
+pub const MAX_CELLS: usize = 32 * 32;
+
+/// Most sprites a level can place. Spawns past this are ignored.
+pub const MAX_SPRITES: usize = 16;
+
+/// Size of the flash slot for a binary level, at the very end of the 2 MB flash. `memory.x`
+/// keeps the firmware out of it, so flashing a new firmware doesn't erase the level.
+pub const FLASH_MAX_LEN: usize = 64 * 1024;
+
+/// Address of the binary level slot in memory mapped flash. Levels are built on the host with
+/// the `encode-level` tool, and loaded with:
+///
+/// ```text
+/// picotool load -o 0x101f0000 level.lvl
+/// ```
+pub const FLASH_ADDRESS: usize = 0x1000_0000 + 2048 * 1024 - FLASH_MAX_LEN;
+
+/// [`Spawn`] kind for a barrel.
+pub const BARREL: u8 = 1;
+/// [`Spawn`] kind for a lamp.
+pub const LAMP: u8 = 2;
+
+/// A single room with two rows of pillars, using every built-in wall and floor texture.
+pub static DEMO: &str = include_str!("levels/demo.map");
+
+/// The sprites placed in [`DEMO`].
+pub static DEMO_SPAWNS: [Spawn; 3] = [
+    tile_center(BARREL, 2, 7),
+    tile_center(LAMP, 4, 4),
+    tile_center(BARREL, 7, 2),
+];
+
+const fn tile_center(kind: u8, x: i32, y: i32) -> Spawn {
+    Spawn::new(
+        kind,
+        Fixed::from_int(x).saturating_add(Fixed::HALF),
+        Fixed::from_int(y).saturating_add(Fixed::HALF),
+        Angle::ZERO,
+    )
+}
+
+/// Returns the sprite for a spawn, or `None` if its kind isn't a sprite.
+pub fn sprite(spawn: &Spawn) -> Option<Sprite> {
+    let texture = match spawn.kind {
+        BARREL => &textures::BARREL_SPRITE,
+        LAMP => &textures::LAMP_SPRITE,
+        _ => return None,
+    };
+    Some(Sprite::new(spawn.x, spawn.y, texture))
+}
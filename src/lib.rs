@@ -0,0 +1,26 @@
+//! The parts of the game that don't depend on the PicoSystem's hardware, so they can be built
+//! and tested on the host:
+//!
+//! ```text
+//! cargo test --lib --target x86_64-unknown-linux-gnu
+//! ```
+#![no_std]
+// Constructors are `const fn new()` so they can initialize statics, which `Default` can't.
+#![allow(clippy::new_without_default)]
+
+#[cfg(test)]
+extern crate std;
+
+pub mod controls;
+pub mod dirty;
+pub mod dma;
+pub mod double_buffer;
+pub mod game_loop;
+pub mod input;
+pub mod levels;
+pub mod panel;
+pub mod raycaster;
+pub mod textures;
+
+#[cfg(test)]
+mod testing;
@@ -1,71 +1,174 @@
 #![no_std]
 #![no_main]
 
+mod dual_core;
 mod picosystem;
-mod raycaster;
 mod st7789;
 
+use core::mem::size_of;
+
 use picosystem::PicoSystem;
+use raycaster_embedded_rs::controls::{Controls, Preset};
+use raycaster_embedded_rs::dirty::DirtyRects;
+use raycaster_embedded_rs::double_buffer::{self, DoubleBuffer};
+use raycaster_embedded_rs::game_loop::{GameLoop, TimeSource};
+use raycaster_embedded_rs::input::{Input, InputConfig};
+use raycaster_embedded_rs::raycaster::{
+    self, parse_map, Generator, Layout, Level, MapStorage, Movement, Player, Region, Renderer,
+    Sprite,
+};
+use raycaster_embedded_rs::{levels, textures};
 use rp_pico::entry;
-use micromath::F32Ext;
-use raycaster::degrees_to_radians;
 
 // Ensure we halt the program on panic (if we don't mention this crate it won't
 // be linked)
 use panic_halt as _;
 
+/// Simulation steps per second. Movement speeds are per step, so the game runs at the same
+/// speed whatever the frame rate.
+const STEPS_PER_SECOND: u32 = 30;
+
+/// Width and height of generated levels, in tiles. Odd, so mazes have walls on every side.
+const GENERATED_SIZE: usize = 31;
+
+const SCREEN_WIDTH: u16 = PicoSystem::PANEL.width;
+const SCREEN_HEIGHT: u16 = PicoSystem::PANEL.height;
+/// Where the screen is split between the two cores. Rays are cast one per pixel column, so this
+/// is also where the columns are split.
+const HALF_WIDTH: u16 = SCREEN_WIDTH / 2;
+/// RAM for the two frame buffers. Two whole 240x240 frames would take 225 KB, so frames are
+/// drawn in 120 row strips, two of which take 113 KB.
+const FRAME_BUFFER_BUDGET: usize = 128 * 1024;
+/// Most separate parts of the screen sent in one frame.
+const DIRTY_RECTS: usize = 4;
+const FRAME_BUFFER_LEN: usize =
+    double_buffer::buffer_len(SCREEN_WIDTH, SCREEN_HEIGHT, FRAME_BUFFER_BUDGET);
+
+// Statics rather than locals, since DMA transfers outlive any borrow of a local, and since they
+// wouldn't fit on the stack.
+static mut FRAME_BUFFERS: [[u16; FRAME_BUFFER_LEN]; 2] = [[0; FRAME_BUFFER_LEN]; 2];
+
+/// The RP2040's RAM.
+const RAM: usize = 256 * 1024;
+/// RAM kept for everything not counted below: other statics, and the rest of the locals.
+const RAM_HEADROOM: usize = 32 * 1024;
+
+// Besides the frame buffers, RAM holds core 1's stack and this core's stack, neither with a
+// guard below it. This core's stack holds the raycaster with its trig table, the map storage,
+// the sprites and the column hits.
+const _: () = assert!(
+    size_of::<[[u16; FRAME_BUFFER_LEN]; 2]>()
+        + dual_core::CORE1_STACK_LEN
+        + size_of::<raycaster::Raycaster<'static>>()
+        + size_of::<MapStorage<{ levels::MAX_CELLS }>>()
+        + size_of::<heapless::Vec<Sprite, { levels::MAX_SPRITES }>>()
+        + size_of::<[Option<raycaster::ColumnHit>; SCREEN_WIDTH as usize]>()
+        + RAM_HEADROOM
+        <= RAM
+);
+
 #[entry]
 fn main() -> ! {
-    let mut device = PicoSystem::take().unwrap();
-    let mut frame_buffer = [u16::from_be(0xf800_u16); 240 * 240];
-    device.display.set_pixels(&frame_buffer).unwrap();
+    let device = PicoSystem::take().unwrap();
+    // Only taken here, and `main` never returns, so these are the only references to them.
+    let [first, second] = unsafe { &mut *core::ptr::addr_of_mut!(FRAME_BUFFERS) };
+    let mut core1 = device.core1;
+    let mut frame = DoubleBuffer::new(
+        device.display,
+        device.display_dma,
+        first,
+        second,
+        SCREEN_WIDTH,
+        SCREEN_HEIGHT,
+    );
 
-    let mut raycaster = raycaster::Raycaster::new();
-    loop {
-        if device.button_right.is_pressed() {
-            raycaster.player.angle_deg += 4;
+    // Holding X or Y at boot plays a generated maze or rooms level. Otherwise, a level loaded
+    // into the flash slot replaces the built-in one. The slot is outside of the firmware, and
+    // flash is never written while the game runs.
+    let mut storage = MapStorage::<{ levels::MAX_CELLS }>::new();
+    let mut sprites = heapless::Vec::<Sprite, { levels::MAX_SPRITES }>::new();
+    let layout = if device.buttons.x.is_pressed() {
+        Some(Layout::Maze)
+    } else if device.buttons.y.is_pressed() {
+        Some(Layout::Rooms)
+    } else {
+        None
+    };
+    let flash_level = unsafe { Level::from_flash(levels::FLASH_ADDRESS, levels::FLASH_MAX_LEN) };
+    let (map, player) = match (layout, flash_level) {
+        (Some(layout), _) => {
+            // Seeded by how long the button was held, since the boot time barely changes.
+            while device.buttons.x.is_pressed() || device.buttons.y.is_pressed() {}
+            let mut generator = Generator::new(layout, GENERATED_SIZE, GENERATED_SIZE);
+            generator.ceiling = 2;
+            generator
+                .generate(device.timer.now_us(), &mut storage)
+                .unwrap()
         }
-
-        if device.button_left.is_pressed() {
-            raycaster.player.angle_deg -= 4;
+        (None, Ok(level)) => {
+            let spawns = level.spawns().filter_map(|spawn| levels::sprite(&spawn));
+            sprites.extend(spawns.take(levels::MAX_SPRITES));
+            (level.map, level.player().unwrap_or_else(Player::new))
         }
-
-        if device.button_up.is_pressed() {
-            let player_cos = f32::cos(degrees_to_radians(raycaster.player.angle_deg)) * 0.2;
-            let player_sin = f32::sin(degrees_to_radians(raycaster.player.angle_deg)) * 0.2;
-            let new_x = raycaster.player.x + player_cos;
-            let new_y = raycaster.player.y + player_sin;
-            if let Some(0) = raycaster.map.tile_at(new_x as usize, new_y as usize) {
-                raycaster.player.x = new_x;
-                raycaster.player.y = new_y;
-            }
+        (None, Err(_)) => {
+            let spawns = levels::DEMO_SPAWNS.iter().filter_map(levels::sprite);
+            sprites.extend(spawns);
+            parse_map(levels::DEMO, &mut storage).unwrap()
+        }
+    };
+    let mut raycaster = raycaster::Raycaster::new(map);
+    raycaster.screen_width = SCREEN_WIDTH;
+    raycaster.screen_height = SCREEN_HEIGHT;
+    raycaster.columns = SCREEN_WIDTH;
+    let movement = Movement::new();
+    let renderer = Renderer::new(&textures::WALLS, &textures::FLATS);
+    let mut columns = [None; SCREEN_WIDTH as usize];
+    let controls = Controls::preset(Preset::HoldToStrafe);
+    let mut input = Input::new(InputConfig::new());
+    let mut game_loop = GameLoop::new(&device.timer, STEPS_PER_SECOND);
+    // The player as of the last two simulation steps. The view is drawn in between them.
+    let mut player = player;
+    let mut previous = player;
+    // Only the parts of the screen that changed are drawn and sent. The whole view changes
+    // whenever the player moves, and nothing does while they stand still.
+    let mut dirty = DirtyRects::<DIRTY_RECTS>::new(SCREEN_WIDTH, SCREEN_HEIGHT);
+    dirty.mark_all();
+    loop {
+        input.update(&device.buttons, &device.timer);
+        let move_input = controls.move_input(&input);
+        for _ in 0..game_loop.advance() {
+            previous = player;
+            movement.update(&mut player, move_input, &raycaster.trig, &raycaster.map);
         }
 
-        if device.button_down.is_pressed() {
-            let player_cos = f32::cos(degrees_to_radians(raycaster.player.angle_deg)) * 0.2;
-            let player_sin = f32::sin(degrees_to_radians(raycaster.player.angle_deg)) * 0.2;
-            let new_x = raycaster.player.x - player_cos;
-            let new_y = raycaster.player.y - player_sin;
-            if let Some(0) = raycaster.map.tile_at(new_x as usize, new_y as usize) {
-                raycaster.player.x = new_x;
-                raycaster.player.y = new_y;
-            }
+        let view = previous.lerp(&player, game_loop.alpha());
+        if view != raycaster.player {
+            raycaster.player = view;
+            dirty.mark_all();
+        }
+        if dirty.is_clean() {
+            continue;
         }
 
-        raycaster.ray_casting(&mut |x1, y1, x2, y2, color| {
-            for row in y1..y2 {
-                if row >= 240 {
-                    continue;
-                }
-                let row_start = (row * 240) as usize;
-                for col in x1..x2 {
-                    if col >= 240 {
-                        continue;
-                    }
-                    frame_buffer[row_start + col as usize] = color;
-                }
-            }
-        });
-        device.display.set_pixels(&frame_buffer).unwrap();
+        // Each core casts the rays for the half of the screen it draws. Returns while the bottom
+        // of the frame is still being sent, so the next update runs alongside it.
+        let (left_columns, right_columns) = columns.split_at_mut(HALF_WIDTH as usize);
+        core1.join(
+            || raycaster.cast_column_range(0..HALF_WIDTH, left_columns),
+            || raycaster.cast_column_range(HALF_WIDTH..SCREEN_WIDTH, right_columns),
+        );
+        renderer.sort_sprites(&raycaster, &mut sprites);
+        frame
+            .draw_rects(dirty.rects(), |rect, buffer| {
+                // Core 1 draws the right half of the screen while this core draws the left half.
+                let region = Region::window(buffer, rect.x..rect.right(), rect.y..rect.bottom());
+                let (mut left, mut right) = region.split_at_column(HALF_WIDTH);
+                core1.join(
+                    || renderer.render_region(&raycaster, &columns, &sprites, &mut left),
+                    || renderer.render_region(&raycaster, &columns, &sprites, &mut right),
+                );
+            })
+            .unwrap();
+        dirty.clear();
     }
 }
@@ -0,0 +1,159 @@
+//! How an ST7789 display controller is wired to its panel, and the command parameters that
+//! follow from it. Kept apart from the driver so they can be tested on the host.
+
+use crate::dirty::Rect;
+
+// MADCTL bits
+const MADCTL_MY: u8 = 0x80; // rows bottom to top
+const MADCTL_MX: u8 = 0x40; // columns right to left
+const MADCTL_MV: u8 = 0x20; // rows and columns swapped
+const MADCTL_BGR: u8 = 0x08;
+const MADCTL_MH: u8 = 0x04; // panel refreshed right to left
+
+/// How the picture is turned on the panel, clockwise.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Rotation {
+    Deg0,
+    Deg90,
+    Deg180,
+    Deg270,
+}
+
+/// Order of the color channels in the panel's pixels.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorOrder {
+    Rgb,
+    Bgr,
+}
+
+/// How an ST7789 controller is wired to its panel.
+///
+/// The controller has room for 240x320 pixels, and smaller panels show part of it. `width` and
+/// `height` are the size of the picture once rotated, and the offsets tell where it starts in
+/// the controller's memory, in the same rotated frame.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PanelConfig {
+    pub width: u16,
+    pub height: u16,
+    pub x_offset: u16,
+    pub y_offset: u16,
+    pub rotation: Rotation,
+    pub color_order: ColorOrder,
+    /// Whether colors are inverted, which most IPS panels need to show them right.
+    pub invert: bool,
+    /// Whether the controller refreshes the panel from its right edge. This only changes the
+    /// scan order, not where the picture is drawn.
+    pub refresh_right_to_left: bool,
+}
+
+impl PanelConfig {
+    /// The PicoSystem's 240x240 screen.
+    pub const PICOSYSTEM: PanelConfig = PanelConfig {
+        width: 240,
+        height: 240,
+        x_offset: 0,
+        y_offset: 0,
+        rotation: Rotation::Deg0,
+        color_order: ColorOrder::Rgb,
+        invert: true,
+        refresh_right_to_left: true,
+    };
+
+    /// The Pico Display's 240x135 screen, in landscape.
+    pub const PICO_DISPLAY: PanelConfig = PanelConfig {
+        width: 240,
+        height: 135,
+        x_offset: 40,
+        y_offset: 53,
+        rotation: Rotation::Deg90,
+        color_order: ColorOrder::Rgb,
+        invert: true,
+        refresh_right_to_left: false,
+    };
+
+    /// Returns the whole screen.
+    pub const fn screen(&self) -> Rect {
+        Rect::new(0, 0, self.width, self.height)
+    }
+
+    /// Returns the MADCTL value that turns the picture by `rotation`, with `color_order` and
+    /// the refresh order.
+    pub fn madctl(&self) -> u8 {
+        let rotation = match self.rotation {
+            Rotation::Deg0 => 0,
+            Rotation::Deg90 => MADCTL_MX | MADCTL_MV,
+            Rotation::Deg180 => MADCTL_MX | MADCTL_MY,
+            Rotation::Deg270 => MADCTL_MY | MADCTL_MV,
+        };
+        let color_order = match self.color_order {
+            ColorOrder::Rgb => 0,
+            ColorOrder::Bgr => MADCTL_BGR,
+        };
+        let refresh = if self.refresh_right_to_left {
+            MADCTL_MH
+        } else {
+            0
+        };
+        rotation | color_order | refresh
+    }
+
+    /// Returns the CASET and RASET parameters that select `rect` of the screen: the first and
+    /// last column, then the first and last row, in the controller's memory.
+    pub fn window(&self, rect: Rect) -> ([u8; 4], [u8; 4]) {
+        let [x0, x1] = (self.x_offset + rect.x).to_be_bytes();
+        let [x2, x3] = (self.x_offset + rect.right() - 1).to_be_bytes();
+        let [y0, y1] = (self.y_offset + rect.y).to_be_bytes();
+        let [y2, y3] = (self.y_offset + rect.bottom() - 1).to_be_bytes();
+        ([x0, x1, x2, x3], [y0, y1, y2, y3])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rotated(rotation: Rotation) -> PanelConfig {
+        PanelConfig {
+            rotation,
+            refresh_right_to_left: false,
+            ..PanelConfig::PICOSYSTEM
+        }
+    }
+
+    #[test]
+    fn madctl_matches_the_panels() {
+        assert_eq!(PanelConfig::PICOSYSTEM.madctl(), 0x04);
+        assert_eq!(PanelConfig::PICO_DISPLAY.madctl(), 0x60);
+    }
+
+    #[test]
+    fn madctl_follows_rotation_and_color_order() {
+        assert_eq!(rotated(Rotation::Deg0).madctl(), 0x00);
+        assert_eq!(rotated(Rotation::Deg90).madctl(), 0x60);
+        assert_eq!(rotated(Rotation::Deg180).madctl(), 0xc0);
+        assert_eq!(rotated(Rotation::Deg270).madctl(), 0xa0);
+
+        let bgr = PanelConfig {
+            color_order: ColorOrder::Bgr,
+            ..rotated(Rotation::Deg90)
+        };
+        assert_eq!(bgr.madctl(), 0x68);
+    }
+
+    #[test]
+    fn windows_are_offset_into_the_controller_memory() {
+        let panel = PanelConfig::PICO_DISPLAY;
+        assert_eq!(
+            panel.window(panel.screen()),
+            ([0, 40, 1, 23], [0, 53, 0, 187])
+        );
+        assert_eq!(
+            panel.window(Rect::new(10, 20, 30, 5)),
+            ([0, 50, 0, 79], [0, 73, 0, 77])
+        );
+        assert_eq!(
+            PanelConfig::PICOSYSTEM.window(Rect::new(0, 0, 240, 240)),
+            ([0, 0, 0, 239], [0, 0, 0, 239])
+        );
+    }
+}
@@ -1,5 +1,5 @@
 use cortex_m::delay::Delay;
-use display_interface_spi::SPIInterface;
+use display_interface_spi::SPIInterfaceNoCS;
 use embedded_hal::{
     digital::v2::{InputPin, OutputPin},
     spi::MODE_3,
@@ -13,7 +13,7 @@ use pimoroni_picosystem::{
     hal::{self, Clock},
     hal::{
         gpio::{
-            bank0::{Gpio18, Gpio20, Gpio21, Gpio22, Gpio23, Gpio4, Gpio5, Gpio9},
+            bank0::{Gpio16, Gpio17, Gpio18, Gpio19, Gpio20, Gpio21, Gpio22, Gpio23, Gpio4, Gpio9},
             Input, Output, Pin, PullDown, PushPull,
         },
         spi::Enabled,
@@ -22,17 +22,85 @@ use pimoroni_picosystem::{
     pac, XOSC_CRYSTAL_FREQ,
 };
 
+use raycaster_embedded_rs::dma::DmaChannel;
+use raycaster_embedded_rs::input::{ButtonId, ButtonSource};
+use raycaster_embedded_rs::panel::PanelConfig;
+
+use super::dual_core::Core1;
 use super::st7789::St7789;
 
 type St7789Display = St7789<
-    SPIInterface<
-        Spi<Enabled, pac::SPI0, 8>,
-        Pin<Gpio9, Output<PushPull>>,
-        Pin<Gpio5, Output<PushPull>>,
-    >,
+    SPIInterfaceNoCS<Spi<Enabled, pac::SPI0, 8>, Pin<Gpio9, Output<PushPull>>>,
     Pin<Gpio4, Output<PushPull>>,
 >;
 
+/// DMA channel used to send frames to the display.
+const DISPLAY_DMA_CHANNEL: usize = 0;
+/// DMA request signal raised when SPI0 can take more data.
+const DREQ_SPI0_TX: u8 = 16;
+
+/// Feeds the display's SPI bus from memory with a DMA channel.
+///
+/// Pixels are sent a byte at a time, in memory order, like the blocking writes of the display
+/// interface do.
+pub struct DisplayDma {
+    dma: pac::DMA,
+    busy: bool,
+}
+
+impl DisplayDma {
+    fn new(dma: pac::DMA, resets: &mut pac::RESETS) -> Self {
+        resets.reset.modify(|_, w| w.dma().clear_bit());
+        while resets.reset_done.read().dma().bit_is_clear() {}
+        // Let SPI0 ask the DMA for data when its FIFO has room.
+        let spi = unsafe { &*pac::SPI0::ptr() };
+        spi.sspdmacr.modify(|_, w| w.txdmae().set_bit());
+        Self { dma, busy: false }
+    }
+}
+
+impl DmaChannel for DisplayDma {
+    unsafe fn start(&mut self, source: *const u16, len: usize) {
+        let spi = &*pac::SPI0::ptr();
+        let channel = &self.dma.ch[DISPLAY_DMA_CHANNEL];
+        channel.ch_read_addr.write(|w| w.bits(source as u32));
+        channel
+            .ch_write_addr
+            .write(|w| w.bits(spi.sspdr.as_ptr() as u32));
+        channel.ch_trans_count.write(|w| w.bits(len as u32 * 2));
+        channel.ch_ctrl_trig.write(|w| {
+            w.data_size().size_byte();
+            w.incr_read().set_bit();
+            w.incr_write().clear_bit();
+            w.treq_sel().bits(DREQ_SPI0_TX);
+            // Chaining to itself turns chaining off.
+            w.chain_to().bits(DISPLAY_DMA_CHANNEL as u8);
+            w.en().set_bit()
+        });
+        self.busy = true;
+    }
+
+    fn is_done(&mut self) -> bool {
+        if !self.busy {
+            return true;
+        }
+        let spi = unsafe { &*pac::SPI0::ptr() };
+        let channel = &self.dma.ch[DISPLAY_DMA_CHANNEL];
+        if channel.ch_ctrl_trig.read().busy().bit_is_set() || spi.sspsr.read().bsy().bit_is_set() {
+            return false;
+        }
+        // Nothing read the bytes received while sending, so the receive FIFO is full of stale
+        // data. Blocking writes wait for one received byte per byte sent, and would return
+        // before their last bytes are out if those were left behind.
+        while spi.sspsr.read().rne().bit_is_set() {
+            let _ = spi.sspdr.read();
+        }
+        spi.sspicr.write(|w| w.roric().set_bit());
+        self.busy = false;
+        true
+    }
+}
+
 pub struct Button<PIN> {
     pin: PIN,
 }
@@ -46,17 +114,46 @@ where
     }
 }
 
+/// Every button on the PicoSystem.
+pub struct Buttons {
+    pub up: Button<Pin<Gpio23, Input<PullDown>>>,
+    pub down: Button<Pin<Gpio20, Input<PullDown>>>,
+    pub left: Button<Pin<Gpio22, Input<PullDown>>>,
+    pub right: Button<Pin<Gpio21, Input<PullDown>>>,
+    pub a: Button<Pin<Gpio18, Input<PullDown>>>,
+    pub b: Button<Pin<Gpio19, Input<PullDown>>>,
+    pub x: Button<Pin<Gpio17, Input<PullDown>>>,
+    pub y: Button<Pin<Gpio16, Input<PullDown>>>,
+}
+
+impl ButtonSource for Buttons {
+    fn is_pressed(&self, button: ButtonId) -> bool {
+        match button {
+            ButtonId::Up => self.up.is_pressed(),
+            ButtonId::Down => self.down.is_pressed(),
+            ButtonId::Left => self.left.is_pressed(),
+            ButtonId::Right => self.right.is_pressed(),
+            ButtonId::A => self.a.is_pressed(),
+            ButtonId::B => self.b.is_pressed(),
+            ButtonId::X => self.x.is_pressed(),
+            ButtonId::Y => self.y.is_pressed(),
+        }
+    }
+}
+
 pub struct PicoSystem {
     pub display: St7789Display,
-    pub button_up: Button<Pin<Gpio23, Input<PullDown>>>,
-    pub button_down: Button<Pin<Gpio20, Input<PullDown>>>,
-    pub button_left: Button<Pin<Gpio22, Input<PullDown>>>,
-    pub button_right: Button<Pin<Gpio21, Input<PullDown>>>,
-    pub button_a: Button<Pin<Gpio18, Input<PullDown>>>,
+    pub display_dma: DisplayDma,
+    pub buttons: Buttons,
     pub timer: Timer,
+    pub core1: Core1,
 }
 
 impl PicoSystem {
+    /// The screen's panel, which the display is set up with. Its `width` and `height` are the
+    /// size of the frames to draw.
+    pub const PANEL: PanelConfig = PanelConfig::PICOSYSTEM;
+
     pub fn take() -> Option<Self> {
         // Grab our singleton objects
         let mut pac = pac::Peripherals::take().unwrap();
@@ -93,7 +190,7 @@ impl PicoSystem {
 
         // Configure ST7789
         let lcd_dc = pins.lcd_dc.into_push_pull_output();
-        let lcd_cs = pins.lcd_cs.into_push_pull_output();
+        let mut lcd_cs = pins.lcd_cs.into_push_pull_output();
         let lcd_reset = pins.lcd_reset.into_push_pull_output();
 
         pins.lcd_mosi.into_mode::<hal::gpio::FunctionSpi>();
@@ -109,41 +206,53 @@ impl PicoSystem {
             &MODE_3,
         );
 
-        let lcd_spi_interface = SPIInterface::new(spi_screen, lcd_dc, lcd_cs);
+        // The display is alone on the bus, so it stays selected, which lets DMA transfers run
+        // without touching the pins.
+        lcd_cs.set_low().unwrap();
+        let lcd_spi_interface = SPIInterfaceNoCS::new(spi_screen, lcd_dc);
         let lcd_delay = Delay::new(core.SYST, clocks.system_clock.freq().raw());
-        let mut display = St7789::new(lcd_spi_interface, Some(lcd_reset), lcd_delay);
+        let mut display = St7789::new(lcd_spi_interface, Some(lcd_reset), lcd_delay, Self::PANEL);
         display.init().unwrap();
 
-        let timer = Timer::new(pac.TIMER, &mut pac.RESETS);
+        let display_dma = DisplayDma::new(pac.DMA, &mut pac.RESETS);
 
-        let button_up = Button {
-            pin: pins.button_up.into_pull_down_input(),
-        };
-
-        let button_down = Button {
-            pin: pins.button_down.into_pull_down_input(),
-        };
-
-        let button_left = Button {
-            pin: pins.button_left.into_pull_down_input(),
-        };
+        let timer = Timer::new(pac.TIMER, &mut pac.RESETS);
 
-        let button_right = Button {
-            pin: pins.button_right.into_pull_down_input(),
+        let buttons = Buttons {
+            up: Button {
+                pin: pins.button_up.into_pull_down_input(),
+            },
+            down: Button {
+                pin: pins.button_down.into_pull_down_input(),
+            },
+            left: Button {
+                pin: pins.button_left.into_pull_down_input(),
+            },
+            right: Button {
+                pin: pins.button_right.into_pull_down_input(),
+            },
+            a: Button {
+                pin: pins.button_a.into_pull_down_input(),
+            },
+            b: Button {
+                pin: pins.button_b.into_pull_down_input(),
+            },
+            x: Button {
+                pin: pins.button_x.into_pull_down_input(),
+            },
+            y: Button {
+                pin: pins.button_y.into_pull_down_input(),
+            },
         };
 
-        let button_a = Button {
-            pin: pins.button_a.into_pull_down_input(),
-        };
+        let core1 = Core1::start(&mut pac.PSM, &mut pac.PPB, sio.fifo);
 
         Some(Self {
             display,
-            button_up,
-            button_down,
-            button_left,
-            button_right,
-            button_a,
+            display_dma,
+            buttons,
             timer,
+            core1,
         })
     }
 }
@@ -0,0 +1,154 @@
+use core::f32::consts::PI;
+use core::ops::{Add, AddAssign, Neg, Sub, SubAssign};
+
+use micromath::F32Ext;
+
+use super::Fixed;
+
+/// Number of [`Angle`] units in a full turn. Must be a power of two, so angles wrap with a mask.
+pub const UNITS_PER_TURN: u16 = 4096;
+const QUARTER_TURN: u16 = UNITS_PER_TURN / 4;
+const UNITS_MASK: u16 = UNITS_PER_TURN - 1;
+
+/// A binary angle, where a full turn is [`UNITS_PER_TURN`] units.
+///
+/// Angles always wrap around, so adding and subtracting them can never overflow, and one unit
+/// (about 0.088 degrees) is fine enough for smooth rotation and per-column ray angles.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Angle(u16);
+
+impl Angle {
+    pub const ZERO: Angle = Angle(0);
+
+    pub const fn from_units(units: u16) -> Self {
+        Self(units & UNITS_MASK)
+    }
+
+    pub const fn units(self) -> u16 {
+        self.0
+    }
+
+    /// Converts whole degrees to the nearest angle.
+    pub const fn from_degrees(deg: i32) -> Self {
+        let deg = deg.rem_euclid(360) as u32;
+        Self::from_units(((deg * UNITS_PER_TURN as u32 + 180) / 360) as u16)
+    }
+
+    pub fn to_radians(self) -> f32 {
+        self.0 as f32 * 2.0 * PI / UNITS_PER_TURN as f32
+    }
+
+    /// Returns `self * numerator / denominator`, treating `self` as a value in `0..UNITS_PER_TURN`.
+    /// Used to split an angle, such as the field of view, into evenly spaced parts.
+    pub const fn scale(self, numerator: u32, denominator: u32) -> Self {
+        Self::from_units((self.0 as u32 * numerator / denominator) as u16)
+    }
+}
+
+impl Add for Angle {
+    type Output = Angle;
+
+    fn add(self, other: Angle) -> Angle {
+        Angle::from_units(self.0.wrapping_add(other.0))
+    }
+}
+
+impl AddAssign for Angle {
+    fn add_assign(&mut self, other: Angle) {
+        *self = *self + other;
+    }
+}
+
+impl Sub for Angle {
+    type Output = Angle;
+
+    fn sub(self, other: Angle) -> Angle {
+        Angle::from_units(self.0.wrapping_sub(other.0))
+    }
+}
+
+impl SubAssign for Angle {
+    fn sub_assign(&mut self, other: Angle) {
+        *self = *self - other;
+    }
+}
+
+impl Neg for Angle {
+    type Output = Angle;
+
+    fn neg(self) -> Angle {
+        Angle::from_units(self.0.wrapping_neg())
+    }
+}
+
+/// Sine and cosine lookup tables for [`Angle`].
+///
+/// Only the first quadrant of the sine wave is stored, and the other quadrants are derived from
+/// its symmetries, so the table takes 4 KB instead of 32 KB for full sine and cosine tables.
+pub struct Trig {
+    quarter_sins: [Fixed; QUARTER_TURN as usize + 1],
+}
+
+impl Trig {
+    pub fn new() -> Self {
+        // The table is built once with soft-float, and only read as fixed point afterwards.
+        // `F32Ext` is named so host builds, which have `std`'s `sin`, get the same table.
+        let mut quarter_sins = [Fixed::ZERO; QUARTER_TURN as usize + 1];
+        for (i, sin) in quarter_sins.iter_mut().enumerate() {
+            *sin = Fixed::from_f32(F32Ext::sin(Angle(i as u16).to_radians()));
+        }
+        // Make sure the peak is exact, even with an approximated `sin`.
+        quarter_sins[QUARTER_TURN as usize] = Fixed::ONE;
+        Self { quarter_sins }
+    }
+
+    pub fn sin(&self, angle: Angle) -> Fixed {
+        let index = (angle.0 % QUARTER_TURN) as usize;
+        match angle.0 / QUARTER_TURN {
+            0 => self.quarter_sins[index],
+            1 => self.quarter_sins[QUARTER_TURN as usize - index],
+            2 => -self.quarter_sins[index],
+            _ => -self.quarter_sins[QUARTER_TURN as usize - index],
+        }
+    }
+
+    pub fn cos(&self, angle: Angle) -> Fixed {
+        self.sin(angle + Angle(QUARTER_TURN))
+    }
+
+    /// Returns the tangent of `angle`, saturating to [`Fixed::MAX`] or [`Fixed::MIN`] where the
+    /// tangent is undefined.
+    pub fn tan(&self, angle: Angle) -> Fixed {
+        self.sin(angle) / self.cos(angle)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn table_stays_close_to_exact_trig() {
+        let trig = Trig::new();
+        for units in 0..UNITS_PER_TURN {
+            let angle = Angle::from_units(units);
+            let radians = angle.to_radians() as f64;
+            assert!((trig.sin(angle).to_f32() as f64 - radians.sin()).abs() < 0.0011);
+            assert!((trig.cos(angle).to_f32() as f64 - radians.cos()).abs() < 0.0011);
+        }
+        assert_eq!(trig.sin(Angle::from_degrees(90)), Fixed::ONE);
+        assert_eq!(trig.cos(Angle::ZERO), Fixed::ONE);
+        assert_eq!(trig.sin(Angle::ZERO), Fixed::ZERO);
+    }
+
+    #[test]
+    fn angles_wrap_around() {
+        let angle = Angle::from_degrees(350) + Angle::from_degrees(20);
+        assert_eq!(angle, Angle::from_degrees(10));
+        assert_eq!(
+            Angle::ZERO - Angle::from_degrees(90),
+            Angle::from_degrees(270)
+        );
+        assert_eq!(-Angle::from_degrees(90), Angle::from_degrees(-90));
+    }
+}
@@ -0,0 +1,236 @@
+use core::ops::{Add, AddAssign, Div, Mul, Neg, Sub, SubAssign};
+
+/// Number of fractional bits in a [`Fixed`]. Changing this trades range for precision across the
+/// whole raycaster: with 16 bits the range is roughly ±32768 and the resolution is 1/65536.
+pub const FRAC_BITS: u32 = 16;
+
+/// A signed fixed-point number, Q16.16 by default (see [`FRAC_BITS`]).
+///
+/// The RP2040 has no FPU, so all per-frame raycaster math uses this type instead of `f32`.
+/// Multiplication and division saturate instead of overflowing, and each of them loses at most
+/// one unit in the last place (1/65536). Addition, subtraction and negation don't saturate:
+/// like `i32`, they panic on overflow in debug builds and wrap in release builds. They're exact,
+/// and can't overflow on map positions and distances, so use [`Fixed::saturating_add`] and
+/// [`Fixed::saturating_sub`] on values derived from large products or quotients.
+/// Conversions from and to `f32` are only meant for setup code, such as building lookup tables.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Fixed(i32);
+
+impl Fixed {
+    pub const ZERO: Fixed = Fixed(0);
+    pub const ONE: Fixed = Fixed(1 << FRAC_BITS);
+    pub const HALF: Fixed = Fixed(1 << (FRAC_BITS - 1));
+    pub const MAX: Fixed = Fixed(i32::MAX);
+    pub const MIN: Fixed = Fixed(i32::MIN);
+    /// The smallest representable positive value.
+    pub const EPSILON: Fixed = Fixed(1);
+
+    pub const fn from_bits(bits: i32) -> Self {
+        Self(bits)
+    }
+
+    pub const fn to_bits(self) -> i32 {
+        self.0
+    }
+
+    pub const fn from_int(n: i32) -> Self {
+        Self(n << FRAC_BITS)
+    }
+
+    /// Converts `n` to the nearest fixed-point number, rounding halves away from zero.
+    pub fn from_f32(n: f32) -> Self {
+        let half = if n < 0.0 { -0.5 } else { 0.5 };
+        Self((n * (1 << FRAC_BITS) as f32 + half) as i32)
+    }
+
+    pub fn to_f32(self) -> f32 {
+        self.0 as f32 / (1 << FRAC_BITS) as f32
+    }
+
+    /// Returns the largest integer less than or equal to `self`.
+    pub const fn to_int(self) -> i32 {
+        self.0 >> FRAC_BITS
+    }
+
+    pub const fn floor(self) -> Self {
+        Self(self.0 & !((1 << FRAC_BITS) - 1))
+    }
+
+    /// Returns the fractional part of `self`, always in `[0, 1)`.
+    pub const fn fract(self) -> Self {
+        Self(self.0 & ((1 << FRAC_BITS) - 1))
+    }
+
+    pub const fn abs(self) -> Self {
+        Self(self.0.saturating_abs())
+    }
+
+    pub const fn saturating_add(self, other: Self) -> Self {
+        Self(self.0.saturating_add(other.0))
+    }
+
+    pub const fn saturating_sub(self, other: Self) -> Self {
+        Self(self.0.saturating_sub(other.0))
+    }
+
+    /// Returns `1 / self`, or [`Fixed::MAX`] when `self` is zero.
+    pub fn recip(self) -> Self {
+        Self::ONE / self
+    }
+
+    /// Returns the square root of `self`, or zero for negative numbers.
+    pub fn sqrt(self) -> Self {
+        if self.0 <= 0 {
+            return Self::ZERO;
+        }
+        Self(isqrt((self.0 as u64) << FRAC_BITS) as i32)
+    }
+
+    /// Returns `sqrt(x * x + y * y)` without overflowing for any `x` and `y` whose result fits.
+    pub fn hypot(x: Self, y: Self) -> Self {
+        let x = x.0 as i64;
+        let y = y.0 as i64;
+        let root = isqrt((x * x + y * y) as u64);
+        Self(root.min(i32::MAX as u64) as i32)
+    }
+
+    fn saturate(bits: i64) -> Self {
+        Self(bits.clamp(i32::MIN as i64, i32::MAX as i64) as i32)
+    }
+}
+
+/// Integer square root, rounded down.
+fn isqrt(n: u64) -> u64 {
+    let mut result = 0;
+    let mut bit = 1 << 62;
+    let mut n = n;
+    while bit > n {
+        bit >>= 2;
+    }
+    while bit != 0 {
+        if n >= result + bit {
+            n -= result + bit;
+            result = (result >> 1) + bit;
+        } else {
+            result >>= 1;
+        }
+        bit >>= 2;
+    }
+    result
+}
+
+impl Add for Fixed {
+    type Output = Fixed;
+
+    fn add(self, other: Fixed) -> Fixed {
+        Fixed(self.0 + other.0)
+    }
+}
+
+impl AddAssign for Fixed {
+    fn add_assign(&mut self, other: Fixed) {
+        self.0 += other.0;
+    }
+}
+
+impl Sub for Fixed {
+    type Output = Fixed;
+
+    fn sub(self, other: Fixed) -> Fixed {
+        Fixed(self.0 - other.0)
+    }
+}
+
+impl SubAssign for Fixed {
+    fn sub_assign(&mut self, other: Fixed) {
+        self.0 -= other.0;
+    }
+}
+
+impl Neg for Fixed {
+    type Output = Fixed;
+
+    fn neg(self) -> Fixed {
+        Fixed(-self.0)
+    }
+}
+
+impl Mul for Fixed {
+    type Output = Fixed;
+
+    fn mul(self, other: Fixed) -> Fixed {
+        Fixed::saturate((self.0 as i64 * other.0 as i64) >> FRAC_BITS)
+    }
+}
+
+impl Mul<i32> for Fixed {
+    type Output = Fixed;
+
+    fn mul(self, other: i32) -> Fixed {
+        Fixed::saturate(self.0 as i64 * other as i64)
+    }
+}
+
+impl Div for Fixed {
+    type Output = Fixed;
+
+    fn div(self, other: Fixed) -> Fixed {
+        if other.0 == 0 {
+            return if self.0 < 0 { Fixed::MIN } else { Fixed::MAX };
+        }
+        Fixed::saturate(((self.0 as i64) << FRAC_BITS) / other.0 as i64)
+    }
+}
+
+impl Div<i32> for Fixed {
+    type Output = Fixed;
+
+    fn div(self, other: i32) -> Fixed {
+        if other == 0 {
+            return if self.0 < 0 { Fixed::MIN } else { Fixed::MAX };
+        }
+        Fixed::saturate(self.0 as i64 / other as i64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_f32_rounds_to_nearest() {
+        let unit = 1.0 / 65536.0;
+        assert_eq!(Fixed::from_f32(0.7 * unit), Fixed::EPSILON);
+        assert_eq!(Fixed::from_f32(0.3 * unit), Fixed::ZERO);
+        assert_eq!(Fixed::from_f32(-0.7 * unit), -Fixed::EPSILON);
+        assert_eq!(Fixed::from_f32(-0.3 * unit), Fixed::ZERO);
+        assert_eq!(Fixed::from_f32(1.5), Fixed::ONE + Fixed::HALF);
+        assert_eq!(Fixed::from_f32(-2.25).to_f32(), -2.25);
+    }
+
+    #[test]
+    fn multiplication_and_division_saturate() {
+        let big = Fixed::from_int(30000);
+        assert_eq!(big * big, Fixed::MAX);
+        assert_eq!(big * -big, Fixed::MIN);
+        assert_eq!(big * 4, Fixed::MAX);
+        assert_eq!(big / Fixed::from_f32(0.01), Fixed::MAX);
+        assert_eq!(-big / Fixed::ZERO, Fixed::MIN);
+        assert_eq!(Fixed::ONE / 0, Fixed::MAX);
+    }
+
+    #[test]
+    fn rounding_loses_at_most_one_unit() {
+        let third = Fixed::ONE / 3;
+        assert!((third.to_f32() - 1.0 / 3.0).abs() <= 1.0 / 65536.0);
+        let product = Fixed::from_f32(1.1) * Fixed::from_f32(2.3);
+        assert!((product.to_f32() - 1.1 * 2.3).abs() <= 2.0 / 65536.0);
+    }
+
+    #[test]
+    fn saturating_add_and_sub_saturate() {
+        assert_eq!(Fixed::MAX.saturating_add(Fixed::ONE), Fixed::MAX);
+        assert_eq!(Fixed::MIN.saturating_sub(Fixed::ONE), Fixed::MIN);
+        assert_eq!(Fixed::ONE.saturating_add(Fixed::HALF), Fixed::from_f32(1.5));
+    }
+}
@@ -0,0 +1,368 @@
+use tinyrand::{Rand, Seeded, Wyrand};
+
+use super::map::DIRECTIONS;
+use super::{Angle, Fixed, Map, MapStorage, Player};
+
+/// Number of random rooms tried by [`Layout::Rooms`]. Rooms that would overlap are skipped.
+const ROOM_ATTEMPTS: u32 = 64;
+const MIN_ROOM_SIZE: usize = 3;
+const MAX_ROOM_SIZE: usize = 8;
+
+/// How a [`Generator`] lays out its maps.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Layout {
+    /// A perfect maze of one tile wide corridors, with exactly one path between any two cells.
+    /// Corridors run along odd rows and columns, so odd widths and heights fit best.
+    Maze,
+    /// Rectangular rooms, each joined to the previous one by a corridor.
+    Rooms,
+}
+
+/// Errors returned by [`Generator::generate`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GenerateError {
+    /// The map is too small for the layout to place anything.
+    TooSmall,
+    /// The map has more cells than the storage can hold.
+    TooLarge { cells: usize, capacity: usize },
+}
+
+/// Builds maps from a seed. The same seed and settings always produce the same map.
+///
+/// Every map is surrounded by walls, and every empty cell can be reached from the player start.
+pub struct Generator {
+    pub layout: Layout,
+    pub width: usize,
+    pub height: usize,
+    /// Wall tile used for every wall.
+    pub wall: u8,
+    /// Floor value used for every cell.
+    pub floor: u8,
+    /// Ceiling value used for every cell.
+    pub ceiling: u8,
+}
+
+impl Generator {
+    pub fn new(layout: Layout, width: usize, height: usize) -> Self {
+        Self {
+            layout,
+            width,
+            height,
+            wall: 1,
+            floor: 0,
+            ceiling: 0,
+        }
+    }
+
+    /// Generates a map into `storage`, returning it with the player standing in an empty cell.
+    pub fn generate<'a, const N: usize>(
+        &self,
+        seed: u64,
+        storage: &'a mut MapStorage<N>,
+    ) -> Result<(Map<'a>, Player), GenerateError> {
+        let cells = self.width * self.height;
+        if cells > N {
+            return Err(GenerateError::TooLarge { cells, capacity: N });
+        }
+        let min_size = match self.layout {
+            Layout::Maze => 3,
+            Layout::Rooms => MIN_ROOM_SIZE + 2,
+        };
+        if self.width < min_size || self.height < min_size {
+            return Err(GenerateError::TooSmall);
+        }
+
+        storage.cells[..cells].fill(self.wall);
+        storage.floors[..cells].fill(self.floor);
+        storage.ceilings[..cells].fill(self.ceiling);
+
+        let mut rand = Wyrand::seed(seed);
+        let mut grid = Grid {
+            width: self.width,
+            height: self.height,
+            cells: &mut storage.cells[..cells],
+        };
+        let (x, y) = match self.layout {
+            Layout::Maze => grid.carve_maze(&mut rand, &mut storage.floors[..cells]),
+            Layout::Rooms => grid.carve_rooms(&mut rand),
+        };
+        // The floor layer is used as scratch space by the maze.
+        storage.floors[..cells].fill(self.floor);
+
+        let angle = grid.open_direction(x, y);
+        let player = Player::at(
+            Fixed::from_int(x as i32) + Fixed::HALF,
+            Fixed::from_int(y as i32) + Fixed::HALF,
+            angle,
+        );
+        Ok((storage.map(self.width, self.height), player))
+    }
+}
+
+/// Returns a random number in `min..=max`.
+fn random_between(rand: &mut Wyrand, min: usize, max: usize) -> usize {
+    min + rand.next_lim_u32((max - min + 1) as u32) as usize
+}
+
+/// The wall layer of a map being generated.
+struct Grid<'a> {
+    width: usize,
+    height: usize,
+    cells: &'a mut [u8],
+}
+
+impl Grid<'_> {
+    fn is_wall(&self, x: usize, y: usize) -> bool {
+        self.cells[y * self.width + x] != 0
+    }
+
+    fn carve(&mut self, x: usize, y: usize) {
+        self.cells[y * self.width + x] = 0;
+    }
+
+    /// Returns the cell `distance` steps from `x`, `y` in `direction`, if it's inside the border
+    /// walls.
+    fn step(
+        &self,
+        x: usize,
+        y: usize,
+        direction: usize,
+        distance: isize,
+    ) -> Option<(usize, usize)> {
+        let (dx, dy) = DIRECTIONS[direction];
+        let x = x as isize + dx * distance;
+        let y = y as isize + dy * distance;
+        if x < 1 || y < 1 || x >= self.width as isize - 1 || y >= self.height as isize - 1 {
+            return None;
+        }
+        Some((x as usize, y as usize))
+    }
+
+    /// Returns the angle facing the first empty neighbour of `x`, `y`, or east if there's none.
+    fn open_direction(&self, x: usize, y: usize) -> Angle {
+        // Same order as `DIRECTIONS`, starting from north.
+        const ANGLES: [i32; 4] = [270, 0, 90, 180];
+        (0..DIRECTIONS.len())
+            .find(|&direction| {
+                matches!(self.step(x, y, direction, 1), Some((x, y)) if !self.is_wall(x, y))
+            })
+            .map_or(Angle::ZERO, |direction| Angle::from_degrees(ANGLES[direction]))
+    }
+
+    /// Carves a maze with a randomized depth-first search, and returns the cell it started from.
+    ///
+    /// Instead of keeping a stack, each carved cell records in `back` the direction it was
+    /// reached from, so backtracking needs no memory beyond the map itself.
+    fn carve_maze(&mut self, rand: &mut Wyrand, back: &mut [u8]) -> (usize, usize) {
+        let start = (1, 1);
+        let (mut x, mut y) = start;
+        self.carve(x, y);
+
+        loop {
+            let mut unvisited = [0; 4];
+            let mut count = 0;
+            for direction in 0..DIRECTIONS.len() {
+                if let Some((nx, ny)) = self.step(x, y, direction, 2) {
+                    if self.is_wall(nx, ny) {
+                        unvisited[count] = direction;
+                        count += 1;
+                    }
+                }
+            }
+
+            if count > 0 {
+                let direction = unvisited[random_between(rand, 0, count - 1)];
+                let (wall_x, wall_y) = self.step(x, y, direction, 1).unwrap();
+                self.carve(wall_x, wall_y);
+                (x, y) = self.step(x, y, direction, 2).unwrap();
+                self.carve(x, y);
+                // The opposite direction, to walk back later.
+                back[y * self.width + x] = ((direction + 2) % 4) as u8;
+            } else if (x, y) == start {
+                return start;
+            } else {
+                let direction = back[y * self.width + x] as usize;
+                (x, y) = self.step(x, y, direction, 2).unwrap();
+            }
+        }
+    }
+
+    /// Places random rooms, joining each one to the previous room, and returns the center of the
+    /// first room.
+    fn carve_rooms(&mut self, rand: &mut Wyrand) -> (usize, usize) {
+        let mut rooms = 0;
+        let mut first = (0, 0);
+        let mut previous = (0, 0);
+
+        for _ in 0..ROOM_ATTEMPTS {
+            let room_width = random_between(rand, MIN_ROOM_SIZE, MAX_ROOM_SIZE.min(self.width - 2));
+            let room_height =
+                random_between(rand, MIN_ROOM_SIZE, MAX_ROOM_SIZE.min(self.height - 2));
+            let left = random_between(rand, 1, self.width - 1 - room_width);
+            let top = random_between(rand, 1, self.height - 1 - room_height);
+            // Keeps a wall between rooms. The first room always fits, since the map starts out
+            // solid.
+            if !self.is_solid(left - 1, top - 1, room_width + 2, room_height + 2) {
+                continue;
+            }
+
+            for y in top..top + room_height {
+                for x in left..left + room_width {
+                    self.carve(x, y);
+                }
+            }
+            let center = (left + room_width / 2, top + room_height / 2);
+            if rooms == 0 {
+                first = center;
+            } else {
+                let horizontal_first = rand.next_lim_u32(2) == 0;
+                self.carve_corridor(previous, center, horizontal_first);
+            }
+            previous = center;
+            rooms += 1;
+        }
+        first
+    }
+
+    /// Returns `true` if every cell of the rectangle is a wall.
+    fn is_solid(&self, left: usize, top: usize, width: usize, height: usize) -> bool {
+        let right = (left + width).min(self.width);
+        let bottom = (top + height).min(self.height);
+        (top..bottom).all(|y| (left..right).all(|x| self.is_wall(x, y)))
+    }
+
+    /// Carves an L-shaped corridor between two cells.
+    fn carve_corridor(&mut self, from: (usize, usize), to: (usize, usize), horizontal_first: bool) {
+        let corner = if horizontal_first {
+            (to.0, from.1)
+        } else {
+            (from.0, to.1)
+        };
+        for (a, b) in [(from, corner), (corner, to)] {
+            for y in a.1.min(b.1)..=a.1.max(b.1) {
+                for x in a.0.min(b.0)..=a.0.max(b.0) {
+                    self.carve(x, y);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+    use std::vec;
+
+    use super::*;
+
+    const CAPACITY: usize = 41 * 41;
+    const SIZES: [(usize, usize); 4] = [(5, 5), (11, 7), (31, 31), (40, 41)];
+
+    /// Returns how many empty cells can be reached from `x`, `y`, walking north, east, south and
+    /// west.
+    fn reachable(map: &Map, x: usize, y: usize) -> usize {
+        let mut seen = vec![false; map.width * map.height];
+        let mut queue = VecDeque::from([(x, y)]);
+        seen[y * map.width + x] = true;
+        let mut count = 0;
+        while let Some((x, y)) = queue.pop_front() {
+            count += 1;
+            for (dx, dy) in DIRECTIONS {
+                let (nx, ny) = ((x as isize + dx) as usize, (y as isize + dy) as usize);
+                if map.tile_at(nx, ny) == Some(0) && !seen[ny * map.width + nx] {
+                    seen[ny * map.width + nx] = true;
+                    queue.push_back((nx, ny));
+                }
+            }
+        }
+        count
+    }
+
+    #[test]
+    fn every_empty_cell_is_reachable_from_the_start() {
+        let mut storage = MapStorage::<CAPACITY>::new();
+        for layout in [Layout::Maze, Layout::Rooms] {
+            for (width, height) in SIZES {
+                for seed in 0..20 {
+                    let generator = Generator::new(layout, width, height);
+                    let (map, player) = generator.generate(seed, &mut storage).unwrap();
+                    let (x, y) = (player.x.to_int() as usize, player.y.to_int() as usize);
+                    assert_eq!(map.tile_at(x, y), Some(0));
+
+                    let empty = map.cells.iter().filter(|&&tile| tile == 0).count();
+                    assert!(empty > 1, "{:?} {}x{} seed {}", layout, width, height, seed);
+                    assert_eq!(
+                        reachable(&map, x, y),
+                        empty,
+                        "{:?} {}x{} seed {}",
+                        layout,
+                        width,
+                        height,
+                        seed
+                    );
+                    for x in 0..width {
+                        assert_ne!(map.tile_at(x, 0), Some(0));
+                        assert_ne!(map.tile_at(x, height - 1), Some(0));
+                    }
+                    for y in 0..height {
+                        assert_ne!(map.tile_at(0, y), Some(0));
+                        assert_ne!(map.tile_at(width - 1, y), Some(0));
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn same_seed_gives_the_same_map() {
+        let mut first = MapStorage::<CAPACITY>::new();
+        let mut second = MapStorage::<CAPACITY>::new();
+        for layout in [Layout::Maze, Layout::Rooms] {
+            let mut generator = Generator::new(layout, 31, 21);
+            generator.floor = 1;
+            generator.ceiling = 2;
+            // Leave different data in one of the storages first.
+            generator.generate(7, &mut second).unwrap();
+            let (a, a_player) = generator.generate(42, &mut first).unwrap();
+            let (b, b_player) = generator.generate(42, &mut second).unwrap();
+            assert_eq!(a.cells, b.cells);
+            assert_eq!(a.floors, b.floors);
+            assert_eq!(a.ceilings, b.ceilings);
+            assert_eq!(
+                (a_player.x, a_player.y, a_player.angle),
+                (b_player.x, b_player.y, b_player.angle)
+            );
+            assert!(a.floors.iter().all(|&floor| floor == 1));
+            assert!(a.ceilings.iter().all(|&ceiling| ceiling == 2));
+
+            let (c, _) = generator.generate(43, &mut second).unwrap();
+            assert_ne!(a.cells, c.cells);
+        }
+    }
+
+    #[test]
+    fn rejects_maps_that_dont_fit() {
+        let mut storage = MapStorage::<100>::new();
+        assert_eq!(
+            Generator::new(Layout::Maze, 11, 11)
+                .generate(0, &mut storage)
+                .err(),
+            Some(GenerateError::TooLarge {
+                cells: 121,
+                capacity: 100
+            })
+        );
+        assert_eq!(
+            Generator::new(Layout::Maze, 2, 10)
+                .generate(0, &mut storage)
+                .err(),
+            Some(GenerateError::TooSmall)
+        );
+        assert_eq!(
+            Generator::new(Layout::Rooms, 10, 4)
+                .generate(0, &mut storage)
+                .err(),
+            Some(GenerateError::TooSmall)
+        );
+    }
+}
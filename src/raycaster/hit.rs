@@ -1,22 +1,105 @@
-use micromath::F32Ext;
+use super::Fixed;
 
-pub enum HitType {
-    Horizontal,
-    Vertical,
+/// Side of a map cell. North is towards row 0, and west towards column 0.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Face {
+    North,
+    South,
+    East,
+    West,
 }
 
+/// Where a ray hit a wall, and what it hit.
+#[derive(Clone, Copy, Debug)]
 pub struct Hit {
-    pub x: f32,
-    pub y: f32,
-    pub hit_type: HitType,
+    /// World position of the hit.
+    pub x: Fixed,
+    pub y: Fixed,
+    /// Distance from the ray's origin to the wall, in multiples of the ray direction's length.
+    /// For rays cast through the projection plane this is the distance along the view direction,
+    /// which doesn't suffer from the fish-eye effect.
+    pub perp_distance: Fixed,
+    /// Side of the map cell that was hit. Always faces back towards the ray's origin.
+    pub face: Face,
+    /// Value of the map cell that was hit.
+    pub tile: u8,
+    /// Column of the map cell that was hit.
+    pub map_x: usize,
+    /// Row of the map cell that was hit.
+    pub map_y: usize,
+    /// Position along the face, in `[0, 1)`, running left to right as seen when looking at the
+    /// face. Used as the horizontal texture coordinate, and to place decals.
+    pub offset: Fixed,
 }
 
 impl Hit {
-    pub fn new(x: f32, y: f32, hit_type: HitType) -> Self {
-        Self { x, y, hit_type }
+    pub fn new(
+        x: Fixed,
+        y: Fixed,
+        perp_distance: Fixed,
+        face: Face,
+        tile: u8,
+        map_x: usize,
+        map_y: usize,
+    ) -> Self {
+        // Coordinates along faces looking towards the positive side of their axis run backwards,
+        // so they're flipped to never mirror textures.
+        let offset = match face {
+            Face::West => y.fract(),
+            Face::East => (Fixed::ONE - y.fract()).fract(),
+            Face::North => (Fixed::ONE - x.fract()).fract(),
+            Face::South => x.fract(),
+        };
+        Self {
+            x,
+            y,
+            perp_distance,
+            face,
+            tile,
+            map_x,
+            map_y,
+            offset,
+        }
     }
 
-    pub fn squared_distance(&self, x: f32, y: f32) -> f32 {
-        f32::powi(self.x - x, 2) + f32::powi(self.y - y, 2)
+    pub fn distance(&self, x: Fixed, y: Fixed) -> Fixed {
+        Fixed::hypot(self.x - x, self.y - y)
+    }
+}
+
+/// What the ray cast for one screen column hit, as filled by
+/// [`super::Raycaster::cast_columns`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ColumnHit {
+    /// Straight-line distance from the player to the hit.
+    pub distance: Fixed,
+    /// Distance from the player to the hit, measured along the view direction. Use this one for
+    /// projection and depth tests, as it doesn't suffer from the fish-eye effect.
+    pub perp_distance: Fixed,
+    /// Value of the map cell that was hit.
+    pub tile: u8,
+    /// Side of the map cell that was hit.
+    pub face: Face,
+    /// Horizontal texture coordinate, in `[0, 1)`, running left to right as seen when facing
+    /// the wall.
+    pub texture_u: Fixed,
+    /// Column of the map cell that was hit.
+    pub map_x: usize,
+    /// Row of the map cell that was hit.
+    pub map_y: usize,
+}
+
+impl ColumnHit {
+    /// Builds the column data for `hit`, found by a ray cast from `origin_x`, `origin_y`.
+    pub fn new(hit: &Hit, origin_x: Fixed, origin_y: Fixed) -> Self {
+        Self {
+            distance: hit.distance(origin_x, origin_y),
+            perp_distance: hit.perp_distance,
+            tile: hit.tile,
+            face: hit.face,
+            texture_u: hit.offset,
+            map_x: hit.map_x,
+            map_y: hit.map_y,
+        }
     }
 }
@@ -0,0 +1,360 @@
+//! A compact binary level container, read in place from flash.
+//!
+//! All values are little endian:
+//!
+//! | Offset | Size            | Contents                                  |
+//! |--------|-----------------|-------------------------------------------|
+//! | 0      | 4               | Magic, `RCLV`                             |
+//! | 4      | 2               | Format version, [`VERSION`]               |
+//! | 6      | 2               | Width                                     |
+//! | 8      | 2               | Height                                    |
+//! | 10     | 2               | Number of spawns                          |
+//! | 12     | width * height  | Wall tiles, row by row                    |
+//! |        | width * height  | Floors, row by row                        |
+//! |        | width * height  | Ceilings, row by row                      |
+//! |        | 12 per spawn    | Spawns, see [`Spawn`]                     |
+//! |        | 4               | CRC-32 (IEEE) of everything before it     |
+//!
+//! Each spawn is a kind byte, a reserved zero byte, the facing [`Angle`] in units, and the
+//! position as [`Fixed`] bits for `x` and then `y`.
+
+use super::{Angle, Fixed, Map, Player};
+
+const MAGIC: [u8; 4] = *b"RCLV";
+/// The only version of the format this build can read.
+pub const VERSION: u16 = 1;
+const HEADER_SIZE: usize = 12;
+const SPAWN_SIZE: usize = 12;
+const CRC_SIZE: usize = 4;
+
+/// Errors found while reading or writing a binary level.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LevelError {
+    /// The data doesn't start with the level magic.
+    BadMagic,
+    /// The level was written with another version of the format.
+    UnsupportedVersion { version: u16 },
+    /// The data is shorter than the size declared by its header.
+    Truncated { expected: usize, actual: usize },
+    /// The stored checksum doesn't match the data.
+    ChecksumMismatch { expected: u32, actual: u32 },
+    /// The level doesn't fit in the output buffer, or its dimensions don't fit in the header.
+    TooLarge,
+}
+
+/// An entity placed in a level, such as the player start or a sprite.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Spawn {
+    /// What to spawn. [`Spawn::PLAYER`] is the player start, other kinds are up to the game.
+    pub kind: u8,
+    pub x: Fixed,
+    pub y: Fixed,
+    pub angle: Angle,
+}
+
+impl Spawn {
+    pub const PLAYER: u8 = 0;
+
+    pub const fn new(kind: u8, x: Fixed, y: Fixed, angle: Angle) -> Self {
+        Self { kind, x, y, angle }
+    }
+
+    fn read(bytes: &[u8]) -> Self {
+        Self {
+            kind: bytes[0],
+            angle: Angle::from_units(read_u16(bytes, 2)),
+            x: Fixed::from_bits(read_u32(bytes, 4) as i32),
+            y: Fixed::from_bits(read_u32(bytes, 8) as i32),
+        }
+    }
+
+    fn write(&self, bytes: &mut [u8]) {
+        bytes[0] = self.kind;
+        bytes[1] = 0;
+        bytes[2..4].copy_from_slice(&self.angle.units().to_le_bytes());
+        bytes[4..8].copy_from_slice(&self.x.to_bits().to_le_bytes());
+        bytes[8..12].copy_from_slice(&self.y.to_bits().to_le_bytes());
+    }
+}
+
+/// A level borrowed from its binary encoding, usually straight from flash.
+#[derive(Clone, Copy)]
+pub struct Level<'a> {
+    pub map: Map<'a>,
+    spawns: &'a [u8],
+}
+
+impl<'a> Level<'a> {
+    /// Checks the header and checksum of an encoded level, and borrows its layers without copying
+    /// them. Any bytes past the end of the level are ignored.
+    pub fn parse(bytes: &'a [u8]) -> Result<Self, LevelError> {
+        let len = encoded_len_from_header(bytes)?;
+        if bytes.len() < len {
+            return Err(LevelError::Truncated {
+                expected: len,
+                actual: bytes.len(),
+            });
+        }
+
+        let (data, crc) = bytes[..len].split_at(len - CRC_SIZE);
+        let expected = read_u32(crc, 0);
+        let actual = crc32(data);
+        if expected != actual {
+            return Err(LevelError::ChecksumMismatch { expected, actual });
+        }
+
+        let width = read_u16(data, 6) as usize;
+        let height = read_u16(data, 8) as usize;
+        let cells = width * height;
+        let (cells, rest) = data[HEADER_SIZE..].split_at(cells);
+        let (floors, rest) = rest.split_at(cells.len());
+        let (ceilings, spawns) = rest.split_at(cells.len());
+        Ok(Self {
+            map: Map::new(width, height, cells, floors, ceilings),
+            spawns,
+        })
+    }
+
+    /// Reads a level stored at `address` in memory mapped (XIP) flash, reading at most `max_len`
+    /// bytes.
+    ///
+    /// # Safety
+    ///
+    /// `address` must point to `max_len` readable bytes that are never written while the level
+    /// is in use.
+    pub unsafe fn from_flash(address: usize, max_len: usize) -> Result<Level<'static>, LevelError> {
+        let header = core::slice::from_raw_parts(address as *const u8, HEADER_SIZE.min(max_len));
+        let len = encoded_len_from_header(header)?.min(max_len);
+        Level::parse(core::slice::from_raw_parts(address as *const u8, len))
+    }
+
+    /// Returns the entities placed in the level, in the order they were encoded.
+    pub fn spawns(&self) -> impl Iterator<Item = Spawn> + 'a {
+        self.spawns.chunks_exact(SPAWN_SIZE).map(Spawn::read)
+    }
+
+    /// Returns the player standing at the level's first [`Spawn::PLAYER`] spawn, if any.
+    pub fn player(&self) -> Option<Player> {
+        self.spawns()
+            .find(|spawn| spawn.kind == Spawn::PLAYER)
+            .map(|spawn| Player::at(spawn.x, spawn.y, spawn.angle))
+    }
+
+    /// Returns the number of bytes needed to encode a level with `map` and `spawns`.
+    pub fn encoded_len(map: &Map, spawns: &[Spawn]) -> usize {
+        HEADER_SIZE + 3 * map.width * map.height + SPAWN_SIZE * spawns.len() + CRC_SIZE
+    }
+
+    /// Encodes a level into the start of `out`, returning the number of bytes written.
+    ///
+    /// This doesn't need `std` either, so the same code builds level files on the host.
+    pub fn encode(map: &Map, spawns: &[Spawn], out: &mut [u8]) -> Result<usize, LevelError> {
+        let too_large = |_| LevelError::TooLarge;
+        let width = u16::try_from(map.width).map_err(too_large)?;
+        let height = u16::try_from(map.height).map_err(too_large)?;
+        let spawn_count = u16::try_from(spawns.len()).map_err(too_large)?;
+        let len = Self::encoded_len(map, spawns);
+        if out.len() < len {
+            return Err(LevelError::TooLarge);
+        }
+
+        out[0..4].copy_from_slice(&MAGIC);
+        out[4..6].copy_from_slice(&VERSION.to_le_bytes());
+        out[6..8].copy_from_slice(&width.to_le_bytes());
+        out[8..10].copy_from_slice(&height.to_le_bytes());
+        out[10..12].copy_from_slice(&spawn_count.to_le_bytes());
+
+        let mut offset = HEADER_SIZE;
+        for layer in [map.cells, map.floors, map.ceilings] {
+            out[offset..offset + layer.len()].copy_from_slice(layer);
+            offset += layer.len();
+        }
+        for spawn in spawns {
+            spawn.write(&mut out[offset..offset + SPAWN_SIZE]);
+            offset += SPAWN_SIZE;
+        }
+        let crc = crc32(&out[..offset]);
+        out[offset..len].copy_from_slice(&crc.to_le_bytes());
+        Ok(len)
+    }
+}
+
+/// Checks the magic and version of a level header, and returns the size of the whole level.
+fn encoded_len_from_header(bytes: &[u8]) -> Result<usize, LevelError> {
+    if bytes.len() < HEADER_SIZE {
+        return Err(LevelError::Truncated {
+            expected: HEADER_SIZE,
+            actual: bytes.len(),
+        });
+    }
+    if bytes[0..4] != MAGIC {
+        return Err(LevelError::BadMagic);
+    }
+    let version = read_u16(bytes, 4);
+    if version != VERSION {
+        return Err(LevelError::UnsupportedVersion { version });
+    }
+
+    // The largest header describes almost 13 GB, which overflows `usize` on 32-bit targets.
+    let cells = read_u16(bytes, 6) as usize * read_u16(bytes, 8) as usize;
+    let spawns = read_u16(bytes, 10) as usize;
+    cells
+        .checked_mul(3)
+        .and_then(|layers| layers.checked_add(HEADER_SIZE + SPAWN_SIZE * spawns + CRC_SIZE))
+        .ok_or(LevelError::TooLarge)
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        bytes[offset],
+        bytes[offset + 1],
+        bytes[offset + 2],
+        bytes[offset + 3],
+    ])
+}
+
+/// CRC-32 (IEEE 802.3), computed bit by bit. Levels are only checked when loaded, so this trades
+/// speed for not keeping a 1 KB table in flash.
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xedb8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use std::vec::Vec;
+
+    use super::*;
+
+    static CELLS: [u8; 6] = [1, 1, 1, 1, 0, 2];
+    static FLOORS: [u8; 6] = [0, 0, 0, 0, 3, 0];
+    static CEILINGS: [u8; 6] = [2, 2, 2, 2, 4, 2];
+
+    fn map() -> Map<'static> {
+        Map::new(3, 2, &CELLS, &FLOORS, &CEILINGS)
+    }
+
+    fn spawns() -> [Spawn; 2] {
+        [
+            Spawn::new(
+                Spawn::PLAYER,
+                Fixed::from_f32(1.5),
+                Fixed::from_f32(1.25),
+                Angle::from_degrees(90),
+            ),
+            Spawn::new(7, Fixed::from_f32(-3.25), Fixed::from_int(4), Angle::ZERO),
+        ]
+    }
+
+    fn encoded() -> Vec<u8> {
+        let mut bytes = [0; 64];
+        let len = Level::encode(&map(), &spawns(), &mut bytes).unwrap();
+        bytes[..len].to_vec()
+    }
+
+    #[test]
+    fn round_trips() {
+        let bytes = encoded();
+        assert_eq!(bytes.len(), Level::encoded_len(&map(), &spawns()));
+        assert_eq!(bytes.len(), 12 + 3 * 6 + 2 * 12 + 4);
+
+        let level = Level::parse(&bytes).unwrap();
+        assert_eq!((level.map.width, level.map.height), (3, 2));
+        assert_eq!(level.map.cells, CELLS);
+        assert_eq!(level.map.floors, FLOORS);
+        assert_eq!(level.map.ceilings, CEILINGS);
+        assert_eq!(level.spawns().collect::<Vec<_>>(), spawns());
+        let player = level.player().unwrap();
+        assert_eq!(
+            (player.x, player.y, player.angle),
+            (spawns()[0].x, spawns()[0].y, spawns()[0].angle)
+        );
+    }
+
+    #[test]
+    fn ignores_bytes_past_the_end() {
+        let mut bytes = encoded();
+        bytes.extend_from_slice(&[0xff; 16]);
+        assert!(Level::parse(&bytes).is_ok());
+        let level = unsafe { Level::from_flash(bytes.as_ptr() as usize, bytes.len()) }.unwrap();
+        assert_eq!(level.map.cells, CELLS);
+    }
+
+    #[test]
+    fn rejects_bad_checksums() {
+        let mut bytes = encoded();
+        bytes[HEADER_SIZE + 4] ^= 1;
+        assert!(matches!(
+            Level::parse(&bytes),
+            Err(LevelError::ChecksumMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn rejects_other_versions() {
+        let mut bytes = encoded();
+        bytes[4..6].copy_from_slice(&(VERSION + 1).to_le_bytes());
+        assert_eq!(
+            Level::parse(&bytes).err(),
+            Some(LevelError::UnsupportedVersion {
+                version: VERSION + 1
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_bad_magic() {
+        let mut bytes = encoded();
+        bytes[0] = b'X';
+        assert_eq!(Level::parse(&bytes).err(), Some(LevelError::BadMagic));
+        // Erased flash.
+        assert_eq!(Level::parse(&[0xff; 64]).err(), Some(LevelError::BadMagic));
+    }
+
+    #[test]
+    fn rejects_truncated_levels() {
+        let bytes = encoded();
+        let len = bytes.len();
+        assert_eq!(
+            Level::parse(&bytes[..len - 1]).err(),
+            Some(LevelError::Truncated {
+                expected: len,
+                actual: len - 1
+            })
+        );
+        assert_eq!(
+            Level::parse(&bytes[..5]).err(),
+            Some(LevelError::Truncated {
+                expected: HEADER_SIZE,
+                actual: 5
+            })
+        );
+        let flash = unsafe { Level::from_flash(bytes.as_ptr() as usize, len - 1) };
+        assert!(matches!(flash, Err(LevelError::Truncated { .. })));
+    }
+
+    #[test]
+    fn rejects_levels_too_large_for_the_output() {
+        let mut bytes = [0; 32];
+        assert_eq!(
+            Level::encode(&map(), &spawns(), &mut bytes).err(),
+            Some(LevelError::TooLarge)
+        );
+    }
+
+    #[test]
+    fn computes_ieee_crc32() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    }
+}
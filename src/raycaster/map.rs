@@ -1,34 +1,369 @@
-pub struct Map {
+use super::{Face, Fixed, Hit, Ray};
+
+/// Grid steps to the north, east, south and west neighbours of a cell.
+pub(super) const DIRECTIONS: [(isize, isize); 4] = [(0, -1), (1, 0), (0, 1), (-1, 0)];
+
+/// A grid of tiles, of any size, stored row by row in borrowed slices.
+///
+/// Borrowing lets levels live in flash as `static` data, or in buffers filled at runtime.
+#[derive(Clone, Copy)]
+pub struct Map<'a> {
     pub width: usize,
     pub height: usize,
-    pub cells: [[u8; 10]; 10],
+    /// Wall tile of each cell. Zero is empty space, any other value is a wall.
+    pub cells: &'a [u8],
+    /// Floor texture of each cell, as an index into [`super::Renderer::flat_textures`].
+    pub floors: &'a [u8],
+    /// Ceiling texture of each cell, as an index into [`super::Renderer::flat_textures`].
+    pub ceilings: &'a [u8],
+}
+
+/// Owned layers for a map of up to `N` cells, for maps built at runtime, such as parsed or
+/// generated levels.
+pub struct MapStorage<const N: usize> {
+    pub cells: [u8; N],
+    pub floors: [u8; N],
+    pub ceilings: [u8; N],
 }
 
-impl Map {
-    pub fn new() -> Self {
+impl<const N: usize> MapStorage<N> {
+    pub const fn new() -> Self {
         Self {
-            width: 10,
-            height: 10,
-            cells: [
-                [1, 1, 1, 1, 1, 1, 1, 1, 1, 1],
-                [1, 0, 0, 0, 0, 0, 0, 0, 0, 1],
-                [1, 0, 0, 0, 0, 0, 0, 0, 0, 1],
-                [1, 0, 0, 1, 0, 0, 1, 0, 0, 1],
-                [1, 0, 0, 1, 0, 0, 1, 0, 0, 1],
-                [1, 0, 0, 1, 0, 0, 1, 0, 0, 1],
-                [1, 0, 0, 1, 0, 0, 1, 0, 0, 1],
-                [1, 0, 0, 0, 0, 0, 0, 0, 0, 1],
-                [1, 0, 0, 0, 0, 0, 0, 0, 0, 1],
-                [1, 1, 1, 1, 1, 1, 1, 1, 1, 1],
-            ],
+            cells: [0; N],
+            floors: [0; N],
+            ceilings: [0; N],
         }
     }
 
-    pub fn tile_at(&self, x: usize, y: usize) -> Option<u8> {
+    /// Borrows the first `width * height` cells of each layer as a map. Panics if they don't fit.
+    pub fn map(&self, width: usize, height: usize) -> Map<'_> {
+        let len = width * height;
+        Map::new(
+            width,
+            height,
+            &self.cells[..len],
+            &self.floors[..len],
+            &self.ceilings[..len],
+        )
+    }
+}
+
+impl<'a> Map<'a> {
+    /// Creates a `width` by `height` map. Every layer must hold exactly `width * height` cells,
+    /// stored row by row.
+    pub const fn new(
+        width: usize,
+        height: usize,
+        cells: &'a [u8],
+        floors: &'a [u8],
+        ceilings: &'a [u8],
+    ) -> Self {
+        assert!(cells.len() == width * height);
+        assert!(floors.len() == width * height);
+        assert!(ceilings.len() == width * height);
+        Self {
+            width,
+            height,
+            cells,
+            floors,
+            ceilings,
+        }
+    }
+
+    /// Returns the index of the cell at `x`, `y` in each layer, if it's inside the map.
+    fn index(&self, x: usize, y: usize) -> Option<usize> {
         if x >= self.width || y >= self.height {
             return None;
         }
 
-        Some(self.cells[y][x])
+        Some(y * self.width + x)
+    }
+
+    pub fn tile_at(&self, x: usize, y: usize) -> Option<u8> {
+        self.index(x, y).map(|index| self.cells[index])
+    }
+
+    pub fn floor_at(&self, x: usize, y: usize) -> Option<u8> {
+        self.index(x, y).map(|index| self.floors[index])
+    }
+
+    pub fn ceiling_at(&self, x: usize, y: usize) -> Option<u8> {
+        self.index(x, y).map(|index| self.ceilings[index])
+    }
+
+    /// Walks the map grid along `ray` using a DDA (digital differential analyzer), visiting
+    /// every cell crossed by the ray exactly once, and returns the first cell for which `blocks`
+    /// returns `true`. The cell the ray starts in is never reported.
+    ///
+    /// Returns `None` if the ray travels `max_distance` (in multiples of the ray direction's
+    /// length) or leaves the map without being blocked.
+    pub fn cast<F>(&self, ray: &Ray, max_distance: Fixed, blocks: F) -> Option<Hit>
+    where
+        F: Fn(u8) -> bool,
+    {
+        let mut map_x = ray.x.to_int();
+        let mut map_y = ray.y.to_int();
+
+        // Distance along the ray between two consecutive vertical (or horizontal) grid lines. A
+        // ray with no horizontal (or vertical) component never crosses one of those lines.
+        let delta_x = ray.dir_x.abs().recip();
+        let delta_y = ray.dir_y.abs().recip();
+
+        // Direction to step in the grid, and distance along the ray to the first vertical (or
+        // horizontal) grid line.
+        let (step_x, mut side_x) = if ray.dir_x < Fixed::ZERO {
+            (-1, ray.x.fract() * delta_x)
+        } else {
+            (1, (Fixed::ONE - ray.x.fract()) * delta_x)
+        };
+        let (step_y, mut side_y) = if ray.dir_y < Fixed::ZERO {
+            (-1, ray.y.fract() * delta_y)
+        } else {
+            (1, (Fixed::ONE - ray.y.fract()) * delta_y)
+        };
+
+        loop {
+            // Always cross the nearest grid line first, so no cell is skipped.
+            let (distance, face) = if side_x < side_y {
+                map_x += step_x;
+                let distance = side_x;
+                side_x = side_x.saturating_add(delta_x);
+                (distance, if step_x > 0 { Face::West } else { Face::East })
+            } else {
+                map_y += step_y;
+                let distance = side_y;
+                side_y = side_y.saturating_add(delta_y);
+                (distance, if step_y > 0 { Face::North } else { Face::South })
+            };
+
+            if distance > max_distance || map_x < 0 || map_y < 0 {
+                return None;
+            }
+
+            match self.tile_at(map_x as usize, map_y as usize) {
+                None => return None,
+                Some(tile) if blocks(tile) => {
+                    let (x, y) = ray.point_at(distance);
+                    return Some(Hit::new(
+                        x,
+                        y,
+                        distance,
+                        face,
+                        tile,
+                        map_x as usize,
+                        map_y as usize,
+                    ));
+                }
+                Some(_) => continue,
+            }
+        }
+    }
+
+    /// Returns the first tile for which `blocks` returns `true` on the straight line between the
+    /// two points, such as the wall between an enemy and the player. Tiles beyond the target
+    /// don't count. The hit's distance is the fraction of the way to the target.
+    pub fn cast_to<F>(
+        &self,
+        from_x: Fixed,
+        from_y: Fixed,
+        to_x: Fixed,
+        to_y: Fixed,
+        blocks: F,
+    ) -> Option<Hit>
+    where
+        F: Fn(u8) -> bool,
+    {
+        let ray = Ray::between(from_x, from_y, to_x, to_y);
+        self.cast(&ray, Fixed::ONE, blocks)
+    }
+
+    /// Returns `true` if no tile for which `blocks` returns `true` lies on the straight line
+    /// between the two points. Tiles beyond the target don't count.
+    pub fn line_of_sight<F>(
+        &self,
+        from_x: Fixed,
+        from_y: Fixed,
+        to_x: Fixed,
+        to_y: Fixed,
+        blocks: F,
+    ) -> bool
+    where
+        F: Fn(u8) -> bool,
+    {
+        self.cast_to(from_x, from_y, to_x, to_y, blocks).is_none()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::vec::Vec;
+
+    use super::*;
+    use crate::testing::map;
+
+    #[rustfmt::skip]
+    const ROOM: [&str; 8] = [
+        "########",
+        "#......#",
+        "#..#...#",
+        "#......#",
+        "#....#.#",
+        "#......#",
+        "#......#",
+        "########",
+    ];
+
+    /// Where a ray reaches a wall cell, found by intersecting the ray with the cell's box,
+    /// independently of the DDA.
+    struct Reach {
+        cell: (usize, usize),
+        distance: f64,
+        /// Whether the ray only touches a corner of the cell.
+        corner: bool,
+    }
+
+    fn reaches(map: &Map, ray: &Ray) -> Vec<Reach> {
+        let origin = [ray.x.to_f32() as f64, ray.y.to_f32() as f64];
+        let dir = [ray.dir_x.to_f32() as f64, ray.dir_y.to_f32() as f64];
+        let start = (ray.x.to_int() as usize, ray.y.to_int() as usize);
+        let mut reaches = Vec::new();
+        for y in 0..map.height {
+            for x in 0..map.width {
+                if map.tile_at(x, y) == Some(0) || (x, y) == start {
+                    continue;
+                }
+                let (mut enter, mut exit) = (f64::NEG_INFINITY, f64::INFINITY);
+                for axis in 0..2 {
+                    let low = [x, y][axis] as f64;
+                    if dir[axis] == 0.0 {
+                        if origin[axis] <= low || origin[axis] >= low + 1.0 {
+                            enter = f64::INFINITY;
+                        }
+                        continue;
+                    }
+                    let a = (low - origin[axis]) / dir[axis];
+                    let b = (low + 1.0 - origin[axis]) / dir[axis];
+                    enter = enter.max(a.min(b));
+                    exit = exit.min(a.max(b));
+                }
+                if enter <= exit && enter >= 0.0 {
+                    reaches.push(Reach {
+                        cell: (x, y),
+                        distance: enter,
+                        corner: enter == exit,
+                    });
+                }
+            }
+        }
+        reaches
+    }
+
+    /// Checks that `ray` hits the first wall it goes into, or a wall whose corner it touches
+    /// before that, at the right distance give or take the rounding of a few [`Fixed`] steps.
+    fn check_against_reference(map: &Map, ray: Ray) {
+        let hit = map.cast(&ray, Fixed::MAX, |tile| tile != 0).unwrap();
+        let reaches = reaches(map, &ray);
+        let nearest = reaches
+            .iter()
+            .filter(|reach| !reach.corner)
+            .map(|reach| reach.distance)
+            .fold(f64::INFINITY, f64::min);
+        let reach = reaches
+            .iter()
+            .find(|reach| reach.cell == (hit.map_x, hit.map_y))
+            .unwrap_or_else(|| panic!("{:?} hit a wall it doesn't reach", ray));
+        assert!(
+            reach.distance == nearest || (reach.corner && reach.distance <= nearest),
+            "{:?} hit {:?} at {}, but goes into a wall at {}",
+            ray,
+            reach.cell,
+            reach.distance,
+            nearest
+        );
+        assert!(
+            (hit.perp_distance.to_f32() as f64 - reach.distance).abs() < 1.0 / 4096.0,
+            "{:?} hit at {}, expected {}",
+            ray,
+            hit.perp_distance.to_f32(),
+            reach.distance
+        );
+    }
+
+    fn f(value: f32) -> Fixed {
+        Fixed::from_f32(value)
+    }
+
+    #[test]
+    fn axis_aligned_rays_hit_the_first_wall() {
+        let map = map(&ROOM);
+        let ray = Ray::new(f(1.25), f(2.5), Fixed::ONE, Fixed::ZERO);
+        let hit = map.cast(&ray, Fixed::MAX, |tile| tile != 0).unwrap();
+        assert_eq!((hit.map_x, hit.map_y, hit.face), (3, 2, Face::West));
+        assert_eq!((hit.x, hit.y), (f(3.0), f(2.5)));
+        assert_eq!(hit.perp_distance, f(1.75));
+
+        for &(x, y) in &[(1.5, 1.5), (2.25, 3.75), (4.5, 4.5), (6.75, 6.125)] {
+            for (dir_x, dir_y) in [(1, 0), (0, 1), (-1, 0), (0, -1)] {
+                let ray = Ray::new(f(x), f(y), Fixed::from_int(dir_x), Fixed::from_int(dir_y));
+                check_against_reference(&map, ray);
+            }
+        }
+    }
+
+    #[test]
+    fn diagonal_rays_hit_the_first_wall() {
+        let map = map(&ROOM);
+        for &(x, y) in &[(1.25, 1.75), (2.125, 3.5), (4.75, 4.25), (6.5, 6.0625)] {
+            for (dir_x, dir_y) in [(1, 1), (1, -1), (-1, 1), (-1, -1), (2, 1), (-1, 3)] {
+                let ray = Ray::new(f(x), f(y), Fixed::from_int(dir_x), Fixed::from_int(dir_y));
+                check_against_reference(&map, ray);
+            }
+        }
+    }
+
+    #[test]
+    fn rays_through_grid_corners_hit_a_wall_at_the_corner() {
+        let map = map(&ROOM);
+        // From cell centers, 45 degree rays only cross the grid at corners.
+        for &(x, y) in &[(1.5, 1.5), (2.5, 3.5), (4.5, 3.5), (6.5, 6.5), (5.5, 1.5)] {
+            for (dir_x, dir_y) in [(1, 1), (1, -1), (-1, 1), (-1, -1)] {
+                let ray = Ray::new(f(x), f(y), Fixed::from_int(dir_x), Fixed::from_int(dir_y));
+                check_against_reference(&map, ray);
+            }
+        }
+        // Going between two walls that only share a corner hits one of them.
+        let map = self::map(&["######", "#....#", "#..#.#", "#.#..#", "#....#", "######"]);
+        let ray = Ray::new(f(1.5), f(1.5), Fixed::ONE, Fixed::ONE);
+        let hit = map.cast(&ray, Fixed::MAX, |tile| tile != 0).unwrap();
+        assert!([(2, 3), (3, 2)].contains(&(hit.map_x, hit.map_y)));
+        assert_eq!(hit.perp_distance, f(1.5));
+    }
+
+    #[test]
+    fn cast_to_finds_the_first_wall_between_two_points() {
+        let map = map(&ROOM);
+        let hit = map
+            .cast_to(f(1.5), f(2.5), f(5.5), f(2.5), |tile| tile != 0)
+            .unwrap();
+        assert_eq!((hit.map_x, hit.map_y, hit.face), (3, 2, Face::West));
+        assert_eq!((hit.x, hit.y), (f(3.0), f(2.5)));
+        assert_eq!(hit.perp_distance, f(0.375));
+        assert!(!map.line_of_sight(f(1.5), f(2.5), f(5.5), f(2.5), |tile| tile != 0));
+
+        // Walls past the target don't count.
+        assert!(map
+            .cast_to(f(1.5), f(2.5), f(2.5), f(2.5), |tile| tile != 0)
+            .is_none());
+        assert!(map.line_of_sight(f(1.5), f(2.5), f(2.5), f(2.5), |tile| tile != 0));
+        // Nor do tiles that don't block.
+        assert!(map.line_of_sight(f(1.5), f(2.5), f(5.5), f(2.5), |tile| tile == 2));
+    }
+
+    #[test]
+    fn rays_stop_at_the_max_distance() {
+        let map = map(&ROOM);
+        let ray = Ray::new(f(1.5), f(2.5), Fixed::ONE, Fixed::ZERO);
+        assert!(map.cast(&ray, Fixed::ONE, |tile| tile != 0).is_none());
+        assert!(map.cast(&ray, f(1.5), |tile| tile != 0).is_some());
     }
 }
@@ -0,0 +1,259 @@
+use super::{Angle, Fixed, Map, Player, Trig};
+
+/// Which way the player is asked to move for one update. Each field is `-1`, `0` or `1`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MoveInput {
+    /// Positive walks forward, negative walks backward.
+    pub forward: i8,
+    /// Positive steps to the right, negative to the left.
+    pub strafe: i8,
+    /// Positive turns right, negative turns left.
+    pub turn: i8,
+}
+
+/// Moves a [`Player`] through a [`Map`], keeping it out of walls.
+///
+/// The player is a square `2 * radius` tiles wide. Each update moves it along the x axis and
+/// then along the y axis, stopping each axis at the first wall on its own, so walking into a
+/// wall at an angle slides along it instead of stopping dead.
+pub struct Movement {
+    /// Distance walked forward or backward per update, in tiles.
+    pub walk_speed: Fixed,
+    /// Distance stepped sideways per update, in tiles.
+    pub strafe_speed: Fixed,
+    /// Angle turned per update.
+    pub turn_speed: Angle,
+    /// Half the width of the player. Must be less than half a tile.
+    pub radius: Fixed,
+}
+
+impl Movement {
+    /// Creates a movement with speeds suited to 30 updates per second.
+    pub fn new() -> Self {
+        Self {
+            // 0.1 tiles.
+            walk_speed: Fixed::from_bits(6554),
+            // 0.075 tiles.
+            strafe_speed: Fixed::from_bits(4915),
+            turn_speed: Angle::from_degrees(3),
+            radius: Fixed::from_bits(Fixed::ONE.to_bits() / 4),
+        }
+    }
+
+    /// Turns and moves `player` as asked by `input`.
+    pub fn update(&self, player: &mut Player, input: MoveInput, trig: &Trig, map: &Map) {
+        match input.turn.signum() {
+            1 => player.angle += self.turn_speed,
+            -1 => player.angle -= self.turn_speed,
+            _ => {}
+        }
+
+        let cos = trig.cos(player.angle);
+        let sin = trig.sin(player.angle);
+        let forward = self.walk_speed * input.forward.signum() as i32;
+        let strafe = self.strafe_speed * input.strafe.signum() as i32;
+        // Right of the view direction is a quarter turn clockwise: (-sin, cos).
+        let dx = cos * forward - sin * strafe;
+        let dy = sin * forward + cos * strafe;
+        self.slide(player, map, dx, dy);
+    }
+
+    /// Moves `player` by `dx`, `dy`, stopping against walls on each axis separately.
+    pub fn slide(&self, player: &mut Player, map: &Map, dx: Fixed, dy: Fixed) {
+        let radius = self.radius;
+
+        if dx != Fixed::ZERO {
+            let x = player.x + dx;
+            if self.fits(map, x, player.y) {
+                player.x = x;
+            } else if dx > Fixed::ZERO {
+                // Stop with the right edge just short of the wall's column.
+                let wall = (x + radius).floor();
+                player.x = player.x.max(wall - radius - Fixed::EPSILON);
+            } else {
+                // Stop with the left edge on the right side of the wall's column.
+                let wall = (x - radius).floor() + Fixed::ONE;
+                player.x = player.x.min(wall + radius);
+            }
+        }
+
+        if dy != Fixed::ZERO {
+            let y = player.y + dy;
+            if self.fits(map, player.x, y) {
+                player.y = y;
+            } else if dy > Fixed::ZERO {
+                let wall = (y + radius).floor();
+                player.y = player.y.max(wall - radius - Fixed::EPSILON);
+            } else {
+                let wall = (y - radius).floor() + Fixed::ONE;
+                player.y = player.y.min(wall + radius);
+            }
+        }
+    }
+
+    /// Returns `true` if the player's square, centered on `x`, `y`, only covers empty cells.
+    pub fn fits(&self, map: &Map, x: Fixed, y: Fixed) -> bool {
+        let left = (x - self.radius).to_int();
+        let right = (x + self.radius).to_int();
+        let top = (y - self.radius).to_int();
+        let bottom = (y + self.radius).to_int();
+        if left < 0 || top < 0 {
+            return false;
+        }
+
+        (top..=bottom).all(|cell_y| {
+            (left..=right).all(|cell_x| map.tile_at(cell_x as usize, cell_y as usize) == Some(0))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tinyrand::{Rand, Seeded, Wyrand};
+
+    use super::super::{parse_map, MapStorage};
+    use super::*;
+    use crate::levels;
+    use crate::testing::map;
+
+    #[rustfmt::skip]
+    const CORRIDOR: [&str; 3] = [
+        "######",
+        "#....#",
+        "######",
+    ];
+
+    fn at(x: f32, y: f32) -> Player {
+        Player::at(Fixed::from_f32(x), Fixed::from_f32(y), Angle::ZERO)
+    }
+
+    #[test]
+    fn fits_only_where_the_square_covers_empty_cells() {
+        let map = map(&CORRIDOR);
+        let movement = Movement::new();
+        let fits = |x, y| movement.fits(&map, Fixed::from_f32(x), Fixed::from_f32(y));
+        assert!(fits(1.5, 1.5));
+        assert!(fits(1.25, 1.25));
+        assert!(fits(4.74, 1.74));
+        assert!(!fits(1.24, 1.5));
+        assert!(!fits(1.5, 1.76));
+        assert!(!fits(-1.5, 1.5));
+        assert!(!fits(7.5, 1.5));
+    }
+
+    #[test]
+    fn stops_flush_against_walls() {
+        let map = map(&CORRIDOR);
+        let movement = Movement::new();
+        let step = Fixed::from_f32(0.3);
+        let mut player = at(1.5, 1.5);
+        for _ in 0..20 {
+            movement.slide(&mut player, &map, step, Fixed::ZERO);
+        }
+        assert_eq!(
+            player.x,
+            Fixed::from_int(5) - movement.radius - Fixed::EPSILON
+        );
+        assert!(movement.fits(&map, player.x, player.y));
+
+        for _ in 0..20 {
+            movement.slide(&mut player, &map, -step, step);
+        }
+        assert_eq!(player.x, Fixed::ONE + movement.radius);
+        assert_eq!(
+            player.y,
+            Fixed::from_int(2) - movement.radius - Fixed::EPSILON
+        );
+    }
+
+    #[test]
+    fn slides_along_walls_at_an_angle() {
+        let map = map(&CORRIDOR);
+        let movement = Movement::new();
+        let mut player = at(1.5, 1.3);
+        movement.slide(
+            &mut player,
+            &map,
+            Fixed::from_f32(0.1),
+            Fixed::from_f32(-0.2),
+        );
+        assert_eq!(player.x, Fixed::from_f32(1.6));
+        assert_eq!(player.y, Fixed::ONE + movement.radius);
+    }
+
+    #[test]
+    fn cant_cut_corners() {
+        #[rustfmt::skip]
+        let map = map(&[
+            "####",
+            "##.#",
+            "#.##",
+            "####",
+        ]);
+        let movement = Movement::new();
+        let mut player = at(1.5, 2.5);
+        for _ in 0..20 {
+            movement.slide(
+                &mut player,
+                &map,
+                Fixed::from_f32(0.1),
+                Fixed::from_f32(-0.1),
+            );
+            assert!(movement.fits(&map, player.x, player.y));
+        }
+        assert_eq!(
+            player.x,
+            Fixed::from_int(2) - movement.radius - Fixed::EPSILON
+        );
+        assert_eq!(player.y, Fixed::from_int(2) + movement.radius);
+    }
+
+    #[test]
+    fn strafing_moves_at_right_angles_to_the_view() {
+        let map = map(&["#####", "#...#", "#...#", "#...#", "#####"]);
+        let movement = Movement::new();
+        let trig = Trig::new();
+        let strafe_right = MoveInput {
+            strafe: 1,
+            ..MoveInput::default()
+        };
+        let forward = MoveInput {
+            forward: 1,
+            ..MoveInput::default()
+        };
+
+        // Facing east, right is south.
+        let mut player = at(2.5, 2.5);
+        movement.update(&mut player, strafe_right, &trig, &map);
+        assert_eq!(player.x, Fixed::from_f32(2.5));
+        assert_eq!(player.y, Fixed::from_f32(2.5) + movement.strafe_speed);
+
+        // Facing south, right is west, and forward is south.
+        let mut player = at(2.5, 2.5);
+        player.angle = Angle::from_degrees(90);
+        movement.update(&mut player, strafe_right, &trig, &map);
+        assert_eq!(player.x, Fixed::from_f32(2.5) - movement.strafe_speed);
+        assert_eq!(player.y, Fixed::from_f32(2.5));
+        movement.update(&mut player, forward, &trig, &map);
+        assert_eq!(player.y, Fixed::from_f32(2.5) + movement.walk_speed);
+    }
+
+    #[test]
+    fn random_walks_never_enter_walls() {
+        let mut storage = MapStorage::<{ levels::MAX_CELLS }>::new();
+        let (map, mut player) = parse_map(levels::DEMO, &mut storage).unwrap();
+        let movement = Movement::new();
+        let trig = Trig::new();
+        let mut rand = Wyrand::seed(1);
+        let mut axis = || rand.next_lim_u32(3) as i8 - 1;
+        for _ in 0..20_000 {
+            let input = MoveInput {
+                forward: axis(),
+                strafe: axis(),
+                turn: axis(),
+            };
+            movement.update(&mut player, input, &trig, &map);
+            assert!(movement.fits(&map, player.x, player.y));
+        }
+    }
+}
@@ -0,0 +1,526 @@
+use heapless::Deque;
+
+use super::map::DIRECTIONS;
+use super::Map;
+
+/// Marks cells that haven't been reached by a search.
+const UNREACHED: u16 = u16::MAX;
+
+/// Marks cells that aren't in an [`OpenSet`].
+const NOT_QUEUED: u16 = u16::MAX;
+
+/// Errors returned by [`Pathfinder::find_path`] and [`FlowField::compute`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PathError {
+    /// The start or goal is blocked or outside the map, or no path joins them.
+    NoPath,
+    /// The map has more cells than the search buffers can hold, or more than 65534.
+    TooLarge { cells: usize, capacity: usize },
+}
+
+/// Checks that a map fits in search buffers of `capacity` cells.
+fn check_size(map: &Map, capacity: usize) -> Result<(), PathError> {
+    let cells = map.width * map.height;
+    if cells > capacity || cells >= UNREACHED as usize {
+        return Err(PathError::TooLarge { cells, capacity });
+    }
+    Ok(())
+}
+
+/// Returns the cells next to `x`, `y` that are inside the map and not blocked, with the index
+/// into [`DIRECTIONS`] of the step to each of them. Agents move between cells that share an
+/// edge, so they never cut wall corners.
+fn open_neighbours<'a, F>(
+    map: &'a Map,
+    x: usize,
+    y: usize,
+    blocks: &'a F,
+) -> impl Iterator<Item = (usize, (usize, usize))> + 'a
+where
+    F: Fn(u8) -> bool,
+{
+    DIRECTIONS
+        .iter()
+        .enumerate()
+        .filter_map(move |(direction, &(dx, dy))| {
+            let x = x.checked_add_signed(dx)?;
+            let y = y.checked_add_signed(dy)?;
+            match map.tile_at(x, y) {
+                Some(tile) if !blocks(tile) => Some((direction, (x, y))),
+                _ => None,
+            }
+        })
+}
+
+/// The cells an A* search has yet to visit, as a binary min-heap of cell indices.
+///
+/// The heap keeps track of where each cell is, so a cell whose cost drops is moved up instead
+/// of being queued again. Each cell is in the heap at most once, so `N` slots always do.
+struct OpenSet<const N: usize> {
+    heap: [u16; N],
+    len: usize,
+    /// Position of each cell in `heap`, or [`NOT_QUEUED`].
+    positions: [u16; N],
+    /// Estimated total cost of each queued cell in the high half, and estimated distance left
+    /// in the low half, so cells with the same total and less left to go come first.
+    keys: [u32; N],
+}
+
+impl<const N: usize> OpenSet<N> {
+    const fn new() -> Self {
+        Self {
+            heap: [0; N],
+            len: 0,
+            positions: [NOT_QUEUED; N],
+            keys: [0; N],
+        }
+    }
+
+    /// Empties the set, for a map of `cells` cells.
+    fn clear(&mut self, cells: usize) {
+        self.positions[..cells].fill(NOT_QUEUED);
+        self.len = 0;
+    }
+
+    /// Queues `cell` with an estimated `total` cost and `left` distance to go, or moves it up
+    /// if it's already queued.
+    fn push(&mut self, cell: usize, total: u16, left: u16) {
+        self.keys[cell] = (total as u32) << 16 | left as u32;
+        let position = match self.positions[cell] {
+            NOT_QUEUED => {
+                self.heap[self.len] = cell as u16;
+                self.positions[cell] = self.len as u16;
+                self.len += 1;
+                self.len - 1
+            }
+            position => position as usize,
+        };
+        self.sift_up(position);
+    }
+
+    /// Removes and returns the cell with the lowest key.
+    fn pop(&mut self) -> Option<usize> {
+        if self.len == 0 {
+            return None;
+        }
+        let cell = self.heap[0] as usize;
+        self.len -= 1;
+        self.swap(0, self.len);
+        self.positions[cell] = NOT_QUEUED;
+        self.sift_down(0);
+        Some(cell)
+    }
+
+    fn key(&self, position: usize) -> u32 {
+        self.keys[self.heap[position] as usize]
+    }
+
+    fn swap(&mut self, a: usize, b: usize) {
+        self.heap.swap(a, b);
+        self.positions[self.heap[a] as usize] = a as u16;
+        self.positions[self.heap[b] as usize] = b as u16;
+    }
+
+    fn sift_up(&mut self, mut position: usize) {
+        while position > 0 {
+            let parent = (position - 1) / 2;
+            if self.key(parent) <= self.key(position) {
+                break;
+            }
+            self.swap(parent, position);
+            position = parent;
+        }
+    }
+
+    fn sift_down(&mut self, mut position: usize) {
+        loop {
+            let mut smallest = position;
+            for child in [2 * position + 1, 2 * position + 2] {
+                if child < self.len && self.key(child) < self.key(smallest) {
+                    smallest = child;
+                }
+            }
+            if smallest == position {
+                break;
+            }
+            self.swap(position, smallest);
+            position = smallest;
+        }
+    }
+}
+
+/// A* search over a [`Map`], for agents that each need their own path.
+///
+/// The search buffers hold up to `N` cells and are reused between searches, so a pathfinder
+/// can be kept in a `static` instead of taking up stack space.
+pub struct Pathfinder<const N: usize> {
+    /// Steps from the start to each cell, or [`UNREACHED`].
+    costs: [u16; N],
+    /// Index into [`DIRECTIONS`] of the step that reached each cell.
+    came_from: [u8; N],
+    /// Cells to visit, ordered by estimated total cost, then by estimated distance left.
+    open: OpenSet<N>,
+}
+
+impl<const N: usize> Pathfinder<N> {
+    pub const fn new() -> Self {
+        Self {
+            costs: [UNREACHED; N],
+            came_from: [0; N],
+            open: OpenSet::new(),
+        }
+    }
+
+    /// Finds a shortest path from `from` to `to` that only crosses cells for which `blocks`
+    /// returns `false`, moving between cells that share an edge.
+    ///
+    /// Writes the first steps of the path into `steps`, starting with the cell after `from` and
+    /// ending with `to` if the path fits, and returns the length of the whole path. Agents can
+    /// pass a short buffer and search again once they reach the end of it.
+    pub fn find_path<F>(
+        &mut self,
+        map: &Map,
+        from: (usize, usize),
+        to: (usize, usize),
+        blocks: F,
+        steps: &mut [(usize, usize)],
+    ) -> Result<usize, PathError>
+    where
+        F: Fn(u8) -> bool,
+    {
+        check_size(map, N)?;
+        match (map.tile_at(from.0, from.1), map.tile_at(to.0, to.1)) {
+            (Some(start), Some(goal)) if !blocks(start) && !blocks(goal) => {}
+            _ => return Err(PathError::NoPath),
+        }
+
+        let width = map.width;
+        let estimate = |(x, y): (usize, usize)| (x.abs_diff(to.0) + y.abs_diff(to.1)) as u16;
+        self.costs[..width * map.height].fill(UNREACHED);
+        self.open.clear(width * map.height);
+
+        self.costs[from.1 * width + from.0] = 0;
+        let h = estimate(from);
+        self.open.push(from.1 * width + from.0, h, h);
+
+        while let Some(index) = self.open.pop() {
+            let cost = self.costs[index];
+            let cell = (index % width, index / width);
+            if cell == to {
+                return Ok(self.write_steps(width, to, cost, steps));
+            }
+
+            for (direction, next) in open_neighbours(map, cell.0, cell.1, &blocks) {
+                let next_index = next.1 * width + next.0;
+                if cost + 1 >= self.costs[next_index] {
+                    continue;
+                }
+                self.costs[next_index] = cost + 1;
+                self.came_from[next_index] = direction as u8;
+                let left = estimate(next);
+                self.open.push(next_index, cost + 1 + left, left);
+            }
+        }
+
+        Err(PathError::NoPath)
+    }
+
+    /// Walks back from `to` to the start, writing the steps that fit into `steps`, and returns
+    /// the path length.
+    fn write_steps(
+        &self,
+        width: usize,
+        to: (usize, usize),
+        len: u16,
+        steps: &mut [(usize, usize)],
+    ) -> usize {
+        let len = len as usize;
+        let mut cell = to;
+        for step in (0..len).rev() {
+            if step < steps.len() {
+                steps[step] = cell;
+            }
+            let (dx, dy) = DIRECTIONS[self.came_from[cell.1 * width + cell.0] as usize];
+            cell = (
+                cell.0.wrapping_add_signed(-dx),
+                cell.1.wrapping_add_signed(-dy),
+            );
+        }
+        len
+    }
+}
+
+/// Distances from every cell of a [`Map`] to a single target, usually the player, so any number
+/// of agents can head towards it by stepping to the neighbour closest to the target.
+///
+/// Holds up to `N` cells. Recompute it when the target moves to another cell.
+pub struct FlowField<const N: usize> {
+    width: usize,
+    height: usize,
+    /// Steps to the target from each cell, or [`UNREACHED`].
+    distances: [u16; N],
+    queue: Deque<usize, N>,
+}
+
+impl<const N: usize> FlowField<N> {
+    pub const fn new() -> Self {
+        Self {
+            width: 0,
+            height: 0,
+            distances: [UNREACHED; N],
+            queue: Deque::new(),
+        }
+    }
+
+    /// Computes the distance from every cell to `target` with a breadth-first search, only
+    /// crossing cells for which `blocks` returns `false`. Fails with [`PathError::NoPath`],
+    /// leaving every cell unreached, if the target is outside the map or blocked.
+    pub fn compute<F>(
+        &mut self,
+        map: &Map,
+        target: (usize, usize),
+        blocks: F,
+    ) -> Result<(), PathError>
+    where
+        F: Fn(u8) -> bool,
+    {
+        check_size(map, N)?;
+        self.width = map.width;
+        self.height = map.height;
+        self.distances[..map.width * map.height].fill(UNREACHED);
+        self.queue.clear();
+
+        match map.tile_at(target.0, target.1) {
+            Some(tile) if !blocks(tile) => {}
+            _ => return Err(PathError::NoPath),
+        }
+        let index = target.1 * map.width + target.0;
+        self.distances[index] = 0;
+        // Every cell is queued at most once, so the queue can't overflow.
+        let _ = self.queue.push_back(index);
+
+        while let Some(index) = self.queue.pop_front() {
+            let distance = self.distances[index];
+            let (x, y) = (index % map.width, index / map.width);
+            for (_, (x, y)) in open_neighbours(map, x, y, &blocks) {
+                let next_index = y * map.width + x;
+                if self.distances[next_index] == UNREACHED {
+                    self.distances[next_index] = distance + 1;
+                    let _ = self.queue.push_back(next_index);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the number of steps from `x`, `y` to the target, if it can be reached.
+    pub fn distance(&self, x: usize, y: usize) -> Option<u16> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        match self.distances[y * self.width + x] {
+            UNREACHED => None,
+            distance => Some(distance),
+        }
+    }
+
+    /// Returns the neighbour of `x`, `y` to move to in order to get closer to the target, or
+    /// `None` at the target itself or where the target can't be reached.
+    pub fn next_step(&self, x: usize, y: usize) -> Option<(usize, usize)> {
+        let distance = self.distance(x, y)?;
+        DIRECTIONS
+            .iter()
+            .filter_map(|&(dx, dy)| {
+                let x = x.checked_add_signed(dx)?;
+                let y = y.checked_add_signed(dy)?;
+                Some((self.distance(x, y)?, (x, y)))
+            })
+            .filter(|&(next, _)| next < distance)
+            .min_by_key(|&(next, _)| next)
+            .map(|(_, cell)| cell)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::map;
+
+    fn blocks(tile: u8) -> bool {
+        tile != 0
+    }
+
+    /// Two ways from the top left to the top right corner: 10 steps through the middle, or 14
+    /// along the bottom.
+    const TWO_WAYS: [&str; 7] = [
+        "#########",
+        "#...#...#",
+        "#.#.#.#.#",
+        "#.#...#.#",
+        "#.#####.#",
+        "#.......#",
+        "#########",
+    ];
+
+    #[test]
+    fn finds_a_shortest_path() {
+        let map = map(&TWO_WAYS);
+        let mut pathfinder = Pathfinder::<64>::new();
+        let mut steps = [(0, 0); 16];
+        let len = pathfinder
+            .find_path(&map, (1, 1), (7, 1), blocks, &mut steps)
+            .unwrap();
+        assert_eq!(len, 10);
+        let mut cell: (usize, usize) = (1, 1);
+        for &step in &steps[..len] {
+            assert_eq!(cell.0.abs_diff(step.0) + cell.1.abs_diff(step.1), 1);
+            assert_eq!(map.tile_at(step.0, step.1), Some(0));
+            cell = step;
+        }
+        assert_eq!(cell, (7, 1));
+    }
+
+    #[test]
+    fn writes_the_first_steps_that_fit() {
+        let map = map(&TWO_WAYS);
+        let mut pathfinder = Pathfinder::<64>::new();
+        let mut steps = [(0, 0); 16];
+        pathfinder
+            .find_path(&map, (1, 1), (7, 1), blocks, &mut steps)
+            .unwrap();
+        let mut first = [(0, 0); 3];
+        let len = pathfinder
+            .find_path(&map, (1, 1), (7, 1), blocks, &mut first)
+            .unwrap();
+        assert_eq!(len, 10);
+        assert_eq!(first, steps[..3]);
+    }
+
+    #[test]
+    fn reports_unreachable_goals() {
+        let map = map(&["#######", "#..#..#", "#######"]);
+        let mut pathfinder = Pathfinder::<64>::new();
+        let mut steps = [(0, 0); 16];
+        assert_eq!(
+            pathfinder.find_path(&map, (1, 1), (5, 1), blocks, &mut steps),
+            Err(PathError::NoPath)
+        );
+    }
+
+    #[test]
+    fn reports_goals_in_walls_or_outside_the_map() {
+        let map = map(&TWO_WAYS);
+        let mut pathfinder = Pathfinder::<64>::new();
+        let mut steps = [(0, 0); 16];
+        assert_eq!(
+            pathfinder.find_path(&map, (1, 1), (2, 2), blocks, &mut steps),
+            Err(PathError::NoPath)
+        );
+        assert_eq!(
+            pathfinder.find_path(&map, (1, 1), (9, 1), blocks, &mut steps),
+            Err(PathError::NoPath)
+        );
+        assert_eq!(
+            pathfinder.find_path(&map, (1, 1), (1, 1), blocks, &mut steps),
+            Ok(0)
+        );
+    }
+
+    #[test]
+    fn reports_maps_larger_than_the_buffers() {
+        let map = map(&TWO_WAYS);
+        let mut pathfinder = Pathfinder::<62>::new();
+        let mut steps = [(0, 0); 16];
+        assert_eq!(
+            pathfinder.find_path(&map, (1, 1), (7, 1), blocks, &mut steps),
+            Err(PathError::TooLarge {
+                cells: 63,
+                capacity: 62
+            })
+        );
+    }
+
+    #[test]
+    fn open_set_queues_each_cell_once() {
+        let mut open = OpenSet::<3>::new();
+        open.clear(3);
+        open.push(0, 9, 0);
+        open.push(1, 8, 0);
+        open.push(2, 7, 0);
+        // Lowering the cost of queued cells moves them up without taking more slots.
+        for total in (0..6).rev() {
+            open.push(0, total, 1);
+            open.push(1, total, 0);
+        }
+        assert_eq!(open.pop(), Some(1));
+        assert_eq!(open.pop(), Some(0));
+        assert_eq!(open.pop(), Some(2));
+        assert_eq!(open.pop(), None);
+    }
+
+    #[test]
+    fn finds_shortest_paths_with_buffers_the_size_of_the_map() {
+        #[rustfmt::skip]
+        let map = map(&[
+            "........",
+            "........",
+            "..#.....",
+            "........",
+            ".....#..",
+            "........",
+            "........",
+            "........",
+        ]);
+        let mut pathfinder = Pathfinder::<64>::new();
+        let mut field = FlowField::<64>::new();
+        let mut steps = [(0, 0); 64];
+        for from in 0..64 {
+            for to in 0..64 {
+                let (from, to) = ((from % 8, from / 8), (to % 8, to / 8));
+                if map.tile_at(from.0, from.1) != Some(0) || map.tile_at(to.0, to.1) != Some(0) {
+                    continue;
+                }
+                field.compute(&map, to, blocks).unwrap();
+                let len = pathfinder
+                    .find_path(&map, from, to, blocks, &mut steps)
+                    .unwrap();
+                assert_eq!(Some(len as u16), field.distance(from.0, from.1));
+            }
+        }
+    }
+
+    #[test]
+    fn flow_field_counts_steps_to_the_target() {
+        let map = map(&["#######", "#...#.#", "#.#...#", "#...#.#", "#######"]);
+        let mut field = FlowField::<64>::new();
+        field.compute(&map, (1, 1), blocks).unwrap();
+        let distances = [
+            [None, None, None, None, None, None, None],
+            [None, Some(0), Some(1), Some(2), None, Some(6), None],
+            [None, Some(1), None, Some(3), Some(4), Some(5), None],
+            [None, Some(2), Some(3), Some(4), None, Some(6), None],
+            [None, None, None, None, None, None, None],
+        ];
+        for (y, row) in distances.iter().enumerate() {
+            for (x, &distance) in row.iter().enumerate() {
+                assert_eq!(field.distance(x, y), distance, "at {}, {}", x, y);
+            }
+        }
+        assert_eq!(field.distance(7, 1), None);
+    }
+
+    #[test]
+    fn flow_field_steps_towards_the_target() {
+        let map = map(&["######", "#..#.#", "######"]);
+        let mut field = FlowField::<64>::new();
+        field.compute(&map, (1, 1), blocks).unwrap();
+        assert_eq!(field.next_step(2, 1), Some((1, 1)));
+        assert_eq!(field.next_step(1, 1), None);
+        assert_eq!(field.next_step(4, 1), None);
+        assert_eq!(field.compute(&map, (9, 9), blocks), Err(PathError::NoPath));
+        // A target in a wall reaches nothing, not even its neighbours.
+        assert_eq!(field.compute(&map, (3, 1), blocks), Err(PathError::NoPath));
+        assert_eq!(field.next_step(2, 1), None);
+        assert_eq!(field.next_step(4, 1), None);
+    }
+}
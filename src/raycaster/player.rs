@@ -1,17 +1,91 @@
+use super::angle::UNITS_PER_TURN;
+use super::{Angle, Fixed};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct Player {
-    pub x: f32,
-    pub y: f32,
-    pub angle_deg: i16,
-    pub fov: i16,
+    pub x: Fixed,
+    pub y: Fixed,
+    pub angle: Angle,
+    pub fov: Angle,
 }
 
 impl Player {
     pub fn new() -> Self {
         Self {
-            x: 1.5,
-            y: 1.5,
-            angle_deg: 0,
-            fov: 60,
+            x: Fixed::from_int(1) + Fixed::HALF,
+            y: Fixed::from_int(1) + Fixed::HALF,
+            angle: Angle::ZERO,
+            fov: Angle::from_degrees(60),
+        }
+    }
+
+    /// Creates a player standing at `x`, `y` and facing `angle`, with the default field of view.
+    pub fn at(x: Fixed, y: Fixed, angle: Angle) -> Self {
+        Self {
+            x,
+            y,
+            angle,
+            ..Self::new()
+        }
+    }
+
+    /// Returns the player `alpha` of the way from `self` to `next`, where `alpha` goes from zero
+    /// to one. Turns take the shorter way around.
+    ///
+    /// Used to draw the player between two simulation steps, see
+    /// [`crate::game_loop::GameLoop::alpha`].
+    pub fn lerp(&self, next: &Player, alpha: Fixed) -> Player {
+        // Angles in the upper half of a turn are turns the other way.
+        let turn = (next.angle - self.angle).units() as i32;
+        let turn = if turn >= UNITS_PER_TURN as i32 / 2 {
+            turn - UNITS_PER_TURN as i32
+        } else {
+            turn
+        };
+        Player {
+            x: self.x + (next.x - self.x) * alpha,
+            y: self.y + (next.y - self.y) * alpha,
+            angle: self.angle + Angle::from_units((Fixed::from_int(turn) * alpha).to_int() as u16),
+            fov: next.fov,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lerp_goes_from_one_state_to_the_next() {
+        let from = Player::at(
+            Fixed::from_int(2),
+            Fixed::from_int(3),
+            Angle::from_units(100),
+        );
+        let to = Player::at(Fixed::from_int(3), Fixed::ONE, Angle::from_units(300));
+        assert_eq!(from.lerp(&to, Fixed::ZERO), from);
+        assert_eq!(from.lerp(&to, Fixed::ONE), to);
+
+        let half = from.lerp(&to, Fixed::HALF);
+        assert_eq!(half.x, Fixed::from_int(2) + Fixed::HALF);
+        assert_eq!(half.y, Fixed::from_int(2));
+        assert_eq!(half.angle, Angle::from_units(200));
+    }
+
+    #[test]
+    fn lerp_turns_the_shorter_way() {
+        let at = |degrees| Player::at(Fixed::ZERO, Fixed::ZERO, Angle::from_degrees(degrees));
+        assert_eq!(
+            at(350).lerp(&at(10), Fixed::HALF).angle,
+            Angle::from_degrees(0)
+        );
+        assert_eq!(
+            at(10).lerp(&at(350), Fixed::HALF).angle,
+            Angle::from_degrees(0)
+        );
+        assert_eq!(
+            at(10).lerp(&at(350), Fixed::from_f32(0.25)).angle,
+            Angle::from_degrees(5)
+        );
+    }
+}
@@ -0,0 +1,38 @@
+use super::{Angle, Fixed, Trig};
+
+/// A ray cast across the map, starting at `x`, `y`.
+///
+/// The direction doesn't need to be normalized. Distances along the ray are measured in
+/// multiples of its length, which lets rays aimed through the projection plane report the
+/// perpendicular distance, and rays aimed at a target report it at distance one.
+#[derive(Clone, Copy, Debug)]
+pub struct Ray {
+    pub x: Fixed,
+    pub y: Fixed,
+    pub dir_x: Fixed,
+    pub dir_y: Fixed,
+}
+
+impl Ray {
+    pub fn new(x: Fixed, y: Fixed, dir_x: Fixed, dir_y: Fixed) -> Self {
+        Self { x, y, dir_x, dir_y }
+    }
+
+    /// A ray pointing at `angle`, with distances measured in tiles.
+    pub fn from_angle(x: Fixed, y: Fixed, angle: Angle, trig: &Trig) -> Self {
+        Self::new(x, y, trig.cos(angle), trig.sin(angle))
+    }
+
+    /// A ray from `from_x`, `from_y` that reaches `to_x`, `to_y` at distance one.
+    pub fn between(from_x: Fixed, from_y: Fixed, to_x: Fixed, to_y: Fixed) -> Self {
+        Self::new(from_x, from_y, to_x - from_x, to_y - from_y)
+    }
+
+    /// Returns the point at `distance` along the ray.
+    pub fn point_at(&self, distance: Fixed) -> (Fixed, Fixed) {
+        (
+            self.x + self.dir_x * distance,
+            self.y + self.dir_y * distance,
+        )
+    }
+}
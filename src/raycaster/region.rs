@@ -0,0 +1,85 @@
+use core::marker::PhantomData;
+use core::ops::Range;
+use core::slice;
+
+/// A rectangle of a frame buffer: some of the screen rows it holds, limited to a range of
+/// columns.
+///
+/// Splitting a region with [`Region::split_at_column`] gives two regions that never share a
+/// pixel, so they can be drawn into at the same time, from different cores.
+pub struct Region<'a> {
+    pixels: *mut u16,
+    /// Pixels per row of the buffer.
+    stride: usize,
+    /// Screen column of the first pixel of each row of the buffer.
+    left: u16,
+    rows: Range<u16>,
+    columns: Range<u16>,
+    _buffer: PhantomData<&'a mut [u16]>,
+}
+
+// Regions never overlap, so like `&mut [u16]` they can be handed to another core.
+unsafe impl Send for Region<'_> {}
+
+impl<'a> Region<'a> {
+    /// Covers all of `buffer`, which holds the screen rows in `rows`, `width` pixels each.
+    ///
+    /// Panics if `buffer` is too short.
+    pub fn new(buffer: &'a mut [u16], width: u16, rows: Range<u16>) -> Self {
+        Self::window(buffer, 0..width, rows)
+    }
+
+    /// Covers all of `buffer`, which holds the screen rows in `rows`, but only the pixels in
+    /// `columns` of each of them, such as a window of the screen sent on its own.
+    ///
+    /// Panics if `buffer` is too short.
+    pub fn window(buffer: &'a mut [u16], columns: Range<u16>, rows: Range<u16>) -> Self {
+        assert!(buffer.len() >= rows.len() * columns.len());
+        Self {
+            pixels: buffer.as_mut_ptr(),
+            stride: columns.len(),
+            left: columns.start,
+            rows,
+            columns,
+            _buffer: PhantomData,
+        }
+    }
+
+    /// Returns the screen rows covered by the region.
+    pub fn rows(&self) -> Range<u16> {
+        self.rows.clone()
+    }
+
+    /// Returns the screen columns covered by the region.
+    pub fn columns(&self) -> Range<u16> {
+        self.columns.clone()
+    }
+
+    /// Returns the pixels of screen row `y` in the region's columns, from left to right.
+    ///
+    /// Panics if `y` isn't one of the region's rows.
+    pub fn row_mut(&mut self, y: u16) -> &mut [u16] {
+        assert!(self.rows.contains(&y));
+        let start = (y - self.rows.start) as usize * self.stride
+            + (self.columns.start - self.left) as usize;
+        // In bounds of the buffer, which `window` checked, and only borrowed through `self`.
+        unsafe { slice::from_raw_parts_mut(self.pixels.add(start), self.columns.len()) }
+    }
+
+    /// Splits the region into the columns left of screen column `x`, and the columns from `x`
+    /// on. Either one is empty if `x` is outside the region.
+    pub fn split_at_column(self, x: u16) -> (Region<'a>, Region<'a>) {
+        let x = x.clamp(self.columns.start, self.columns.end);
+        let left = Region {
+            columns: self.columns.start..x,
+            rows: self.rows.clone(),
+            ..self
+        };
+        let right = Region {
+            columns: x..self.columns.end,
+            rows: self.rows,
+            ..self
+        };
+        (left, right)
+    }
+}
@@ -0,0 +1,525 @@
+use core::cmp::Reverse;
+use core::ops::Range;
+
+use super::{darken, ColumnHit, Face, Fixed, Raycaster, Region, Sprite, Texture, TRANSPARENT};
+
+const COLOR_GRAY: u16 = u16::from_be(0xc638);
+const COLOR_DARKGRAY: u16 = u16::from_be(0x5acb);
+const COLOR_BLUE: u16 = u16::from_be(0x001f);
+const COLOR_LIGHTBLUE: u16 = u16::from_be(0x39df);
+
+/// Converts a screen coordinate to a pixel, clamping it to a screen `size` pixels wide or tall.
+fn to_pixel(value: Fixed, size: u16) -> u16 {
+    value.to_int().clamp(0, size as i32) as u16
+}
+
+/// Draws the view of a [`Raycaster`] into a frame buffer.
+///
+/// Frame buffers are `screen_width` by `screen_height` images, stored row by row, in the same
+/// RGB565 byte order as [`Texture`]s.
+pub struct Renderer {
+    /// Textures for each wall tile. Tile `n` uses `wall_textures[(n - 1) % wall_textures.len()]`,
+    /// and walls are drawn with flat colors when this is empty.
+    pub wall_textures: &'static [Texture],
+    /// Floor and ceiling textures, indexed by the values in [`super::Map::floors`] and
+    /// [`super::Map::ceilings`] modulo their length. Floors and ceilings are drawn with flat
+    /// colors when this is empty.
+    pub flat_textures: &'static [Texture],
+}
+
+impl Renderer {
+    pub fn new(wall_textures: &'static [Texture], flat_textures: &'static [Texture]) -> Self {
+        Self {
+            wall_textures,
+            flat_textures,
+        }
+    }
+
+    /// Casts the view, and draws the floor, ceiling, walls and `sprites` into `frame_buffer`.
+    /// `columns` must hold at least [`Raycaster::columns`] entries, and is left with the cast
+    /// results.
+    pub fn render(
+        &self,
+        raycaster: &Raycaster,
+        columns: &mut [Option<ColumnHit>],
+        sprites: &mut [Sprite],
+        frame_buffer: &mut [u16],
+    ) {
+        self.prepare(raycaster, columns, sprites);
+        let rows = 0..raycaster.screen_height;
+        self.render_rows(raycaster, columns, sprites, rows, frame_buffer);
+    }
+
+    /// Casts the view into `columns`, and sorts `sprites` with [`Renderer::sort_sprites`]. Done
+    /// once per frame, before [`Renderer::render_rows`].
+    pub fn prepare(
+        &self,
+        raycaster: &Raycaster,
+        columns: &mut [Option<ColumnHit>],
+        sprites: &mut [Sprite],
+    ) {
+        raycaster.cast_columns(columns);
+        self.sort_sprites(raycaster, sprites);
+    }
+
+    /// Sorts `sprites` from back to front, so the closest ones are drawn last. Together with
+    /// [`Raycaster::cast_column_range`], this can stand in for [`Renderer::prepare`] when the
+    /// columns are cast in parts.
+    pub fn sort_sprites(&self, raycaster: &Raycaster, sprites: &mut [Sprite]) {
+        let player = &raycaster.player;
+        sprites.sort_unstable_by_key(|sprite| {
+            Reverse(sprite.squared_distance_bits(player.x, player.y))
+        });
+    }
+
+    /// Draws the screen rows in `rows` into `buffer`, which only holds those rows.
+    ///
+    /// `columns` and `sprites` must have been through [`Renderer::prepare`]. Drawing a frame a
+    /// few rows at a time gives the same pixels as drawing it whole.
+    pub fn render_rows(
+        &self,
+        raycaster: &Raycaster,
+        columns: &[Option<ColumnHit>],
+        sprites: &[Sprite],
+        rows: Range<u16>,
+        buffer: &mut [u16],
+    ) {
+        let mut region = Region::new(buffer, raycaster.screen_width, rows);
+        self.render_region(raycaster, columns, sprites, &mut region);
+    }
+
+    /// Draws the part of the view inside `region`.
+    ///
+    /// `columns` and `sprites` must have been through [`Renderer::prepare`]. Regions split with
+    /// [`Region::split_at_column`] can be drawn at the same time on different cores, and give
+    /// the same pixels as drawing the whole frame at once.
+    pub fn render_region(
+        &self,
+        raycaster: &Raycaster,
+        columns: &[Option<ColumnHit>],
+        sprites: &[Sprite],
+        region: &mut Region,
+    ) {
+        self.draw_floor_and_ceiling(raycaster, region);
+        self.draw_walls(raycaster, columns, region);
+        self.draw_sprites(raycaster, sprites, columns, region);
+    }
+
+    /// Returns the texture for a wall tile, if any.
+    fn wall_texture(&self, tile: u8) -> Option<&Texture> {
+        if self.wall_textures.is_empty() || tile == 0 {
+            return None;
+        }
+        Some(&self.wall_textures[(tile as usize - 1) % self.wall_textures.len()])
+    }
+
+    /// Returns the texture for a floor or ceiling value, if any.
+    fn flat_texture(&self, flat: u8) -> Option<&Texture> {
+        if self.flat_textures.is_empty() {
+            return None;
+        }
+        Some(&self.flat_textures[flat as usize % self.flat_textures.len()])
+    }
+
+    /// Returns the color of the floor or ceiling at world position `x`, `y`.
+    fn flat_pixel(&self, flat: Option<u8>, x: Fixed, y: Fixed, fallback: u16) -> u16 {
+        match flat.and_then(|flat| self.flat_texture(flat)) {
+            Some(texture) => texture.pixel(texture.column(x.fract()), texture.row(y.fract())),
+            None => fallback,
+        }
+    }
+
+    /// Fills the rows below the horizon with the floor, and the rows above it with the ceiling.
+    ///
+    /// Every row shows a line on the floor (or on the ceiling) at a constant distance from the
+    /// player, so each row takes a single division, and each pixel a couple of additions.
+    pub fn draw_floor_and_ceiling(&self, raycaster: &Raycaster, region: &mut Region) {
+        let camera = raycaster.camera();
+        let player = &raycaster.player;
+        let map = &raycaster.map;
+        let screen_width = raycaster.screen_width;
+        let screen_height = raycaster.screen_height;
+        let first_x = region.columns().start as i32;
+        let half_screen_height = screen_height / 2;
+        let horizon = screen_height - half_screen_height;
+        // The eye is half a tile above the floor and half a tile below the ceiling, the same
+        // proportions used to draw the walls.
+        let eye_height = camera.projection_distance / 2;
+        // Position of the first pixel's center on the projection plane.
+        let first_camera_x = Fixed::ONE / screen_width as i32 - Fixed::ONE;
+
+        for row in region.rows() {
+            // Ceiling rows mirror floor rows, and see the same line of cells.
+            let is_floor = row >= horizon;
+            let floor_row = if is_floor {
+                row
+            } else {
+                screen_height - 1 - row
+            };
+
+            // Distance to the line on the floor seen through the center of the row.
+            let row_offset = Fixed::from_int((floor_row - half_screen_height) as i32) + Fixed::HALF;
+            let row_distance = eye_height / row_offset;
+
+            // World position seen through each pixel of the row, moving along the plane.
+            let step_x = row_distance * camera.plane_x * 2 / screen_width as i32;
+            let step_y = row_distance * camera.plane_y * 2 / screen_width as i32;
+            let mut world_x = player.x
+                + row_distance * (camera.dir_x + camera.plane_x * first_camera_x)
+                + step_x * first_x;
+            let mut world_y = player.y
+                + row_distance * (camera.dir_y + camera.plane_y * first_camera_x)
+                + step_y * first_x;
+
+            for pixel in region.row_mut(row).iter_mut() {
+                let cell_x = world_x.to_int() as usize;
+                let cell_y = world_y.to_int() as usize;
+                *pixel = if is_floor {
+                    self.flat_pixel(
+                        map.floor_at(cell_x, cell_y),
+                        world_x,
+                        world_y,
+                        COLOR_DARKGRAY,
+                    )
+                } else {
+                    self.flat_pixel(map.ceiling_at(cell_x, cell_y), world_x, world_y, COLOR_GRAY)
+                };
+                world_x += step_x;
+                world_y += step_y;
+            }
+        }
+    }
+
+    /// Draws the walls found by [`Raycaster::cast_columns`], sampling
+    /// [`Renderer::wall_textures`] for every pixel. North and south faces are drawn darker than
+    /// east and west ones, so corners stand out.
+    pub fn draw_walls(
+        &self,
+        raycaster: &Raycaster,
+        columns: &[Option<ColumnHit>],
+        region: &mut Region,
+    ) {
+        let camera = raycaster.camera();
+        let screen_height = raycaster.screen_height;
+        let rows = region.rows();
+        let region_columns = region.columns();
+        let half_screen_height = Fixed::from_int(screen_height as i32 / 2);
+
+        for (column, hit) in columns[..raycaster.columns as usize].iter().enumerate() {
+            // Nothing to draw over the floor and ceiling when no wall is hit.
+            let hit = match hit {
+                Some(hit) => hit,
+                None => continue,
+            };
+
+            let (x, x2) = raycaster.column_pixels(column as u16);
+            let x = x.max(region_columns.start);
+            let x2 = x2.min(region_columns.end);
+            if x >= x2 {
+                continue;
+            }
+            let (x, x2) = (
+                (x - region_columns.start) as usize,
+                (x2 - region_columns.start) as usize,
+            );
+            let mut fill_row = |row: u16, color: u16| region.row_mut(row)[x..x2].fill(color);
+
+            let wall_height = camera.projection_distance / hit.perp_distance;
+            let unclipped_top = half_screen_height - wall_height / 2;
+            let wall_top = to_pixel(unclipped_top, screen_height);
+            let wall_bottom = to_pixel(unclipped_top.saturating_add(wall_height), screen_height);
+            let first_row = wall_top.max(rows.start);
+            let last_row = wall_bottom.min(rows.end).max(first_row);
+            let shaded = matches!(hit.face, Face::North | Face::South);
+
+            match self.wall_texture(hit.tile) {
+                Some(texture) => {
+                    let texture_x = texture.column(hit.texture_u);
+
+                    // Texture rows advanced per screen row, sampled at the center of each pixel.
+                    // Starts part way through the texture when the top of the wall is clipped by
+                    // the screen, or is above the rows being drawn.
+                    let step = Fixed::from_int(texture.height as i32) / wall_height;
+                    let mut texture_y =
+                        (Fixed::from_int(wall_top as i32) + Fixed::HALF - unclipped_top) * step
+                            + step * (first_row - wall_top) as i32;
+                    for row in first_row..last_row {
+                        let color = texture.pixel(texture_x, texture_y.to_int());
+                        fill_row(row, if shaded { darken(color) } else { color });
+                        texture_y += step;
+                    }
+                }
+                None => {
+                    let color = if shaded { COLOR_BLUE } else { COLOR_LIGHTBLUE };
+                    for row in first_row..last_row {
+                        fill_row(row, color);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Draws `sprites` over the walls, using the distances in `columns` to hide the parts of
+    /// sprites that are behind walls.
+    ///
+    /// Sprites are drawn in order, so they must be sorted from back to front, as done by
+    /// [`Renderer::prepare`].
+    pub fn draw_sprites(
+        &self,
+        raycaster: &Raycaster,
+        sprites: &[Sprite],
+        columns: &[Option<ColumnHit>],
+        region: &mut Region,
+    ) {
+        let camera = raycaster.camera();
+        let player = &raycaster.player;
+        let screen_width = raycaster.screen_width;
+        let screen_height = raycaster.screen_height;
+        let rows = region.rows();
+        let region_columns = region.columns();
+        let half_screen_width = Fixed::from_int(screen_width as i32 / 2);
+        let half_screen_height = Fixed::from_int(screen_height as i32 / 2);
+        // Sprites closer than this would be huge, and are mostly inside the player anyway.
+        let near_plane = Fixed::from_bits(Fixed::ONE.to_bits() / 8);
+
+        for sprite in sprites.iter() {
+            // Position relative to the player, along the view direction and across it.
+            let dx = sprite.x - player.x;
+            let dy = sprite.y - player.y;
+            let depth = dx * camera.dir_x + dy * camera.dir_y;
+            if depth < near_plane {
+                continue;
+            }
+            let lateral = dy * camera.dir_x - dx * camera.dir_y;
+
+            // Saturating, as sprites right next to the near plane or far to the side end up far
+            // off the screen.
+            let size = camera.projection_distance / depth;
+            let center_x =
+                half_screen_width.saturating_add(camera.projection_distance * lateral / depth);
+            let left = center_x.saturating_sub(size / 2);
+            let top = half_screen_height.saturating_sub(size / 2);
+
+            let texture = sprite.texture;
+            let step_x = Fixed::from_int(texture.width as i32) / size;
+            let step_y = Fixed::from_int(texture.height as i32) / size;
+            let max_x = texture.width as i32 - 1;
+            let max_y = texture.height as i32 - 1;
+            let first_x = to_pixel(left, screen_width).max(region_columns.start);
+            let last_x = to_pixel(left.saturating_add(size), screen_width).min(region_columns.end);
+            let top_y = to_pixel(top, screen_height);
+            let first_y = top_y.max(rows.start);
+            let last_y = to_pixel(top.saturating_add(size), screen_height)
+                .min(rows.end)
+                .max(first_y);
+
+            for x in first_x..last_x {
+                let column = (x as u32 * raycaster.columns as u32 / screen_width as u32) as usize;
+                let wall_distance = columns[column].map_or(Fixed::MAX, |hit| hit.perp_distance);
+                if depth >= wall_distance {
+                    continue;
+                }
+
+                // Sampled at the center of each pixel. Rounding can land just outside of the
+                // texture, which must not wrap around to its other edge.
+                let texture_x = ((Fixed::from_int(x as i32) + Fixed::HALF).saturating_sub(left)
+                    * step_x)
+                    .to_int()
+                    .clamp(0, max_x);
+                let mut texture_y =
+                    ((Fixed::from_int(top_y as i32) + Fixed::HALF).saturating_sub(top) * step_y)
+                        .saturating_add(step_y * (first_y - top_y) as i32);
+                for y in first_y..last_y {
+                    let color = texture.pixel(texture_x, texture_y.to_int().clamp(0, max_y));
+                    if color != TRANSPARENT {
+                        region.row_mut(y)[(x - region_columns.start) as usize] = color;
+                    }
+                    texture_y = texture_y.saturating_add(step_y);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::vec;
+    use std::vec::Vec;
+
+    use super::super::{parse_map, Angle, Map, MapStorage};
+    use super::*;
+    use crate::{levels, textures};
+
+    const WIDTH: u16 = 240;
+    const HEIGHT: u16 = 240;
+
+    fn renderer() -> Renderer {
+        Renderer::new(&textures::WALLS, &textures::FLATS)
+    }
+
+    #[test]
+    fn sprites_far_off_the_screen_are_skipped() {
+        let mut storage = MapStorage::<{ levels::MAX_CELLS }>::new();
+        let (map, player) = parse_map(levels::DEMO, &mut storage).unwrap();
+        let (x, y) = (player.x, player.y);
+        let mut raycaster = Raycaster::new(map);
+        raycaster.player = player;
+        raycaster.player.angle = Angle::ZERO;
+        let near = Fixed::from_f32(0.13);
+        let mut sprites = [
+            // Just past the near plane, and so far to the side that it's off by more than
+            // `Fixed` can hold.
+            Sprite::new(x + near, y + Fixed::from_int(300), &textures::BARREL_SPRITE),
+            Sprite::new(x + near, y - Fixed::from_int(300), &textures::LAMP_SPRITE),
+        ];
+        let mut columns = [None; WIDTH as usize];
+        let mut frame = vec![0; WIDTH as usize * HEIGHT as usize];
+        let mut empty = vec![0; WIDTH as usize * HEIGHT as usize];
+        renderer().render(&raycaster, &mut columns, &mut sprites, &mut frame);
+        renderer().render(&raycaster, &mut columns, &mut [], &mut empty);
+        assert!(frame == empty);
+    }
+
+    #[test]
+    fn split_halves_match_a_single_pass() {
+        let mut storage = MapStorage::<{ levels::MAX_CELLS }>::new();
+        let (map, _) = parse_map(levels::DEMO, &mut storage).unwrap();
+        let mut raycaster = Raycaster::new(map);
+        let sprites = || -> Vec<Sprite> {
+            levels::DEMO_SPAWNS
+                .iter()
+                .filter_map(levels::sprite)
+                .collect()
+        };
+        let half = WIDTH / 2;
+        let strip = HEIGHT / 2;
+
+        for &(x, y, degrees) in &[(1.5, 1.5, 45), (4.2, 6.8, 300), (8.5, 8.5, 200)] {
+            raycaster.player.x = Fixed::from_f32(x);
+            raycaster.player.y = Fixed::from_f32(y);
+            raycaster.player.angle = Angle::from_degrees(degrees);
+
+            let mut whole_columns = [None; WIDTH as usize];
+            let mut whole = vec![0; WIDTH as usize * HEIGHT as usize];
+            let mut whole_sprites = sprites();
+            renderer().render(
+                &raycaster,
+                &mut whole_columns,
+                &mut whole_sprites,
+                &mut whole,
+            );
+
+            // Cast and drawn the way the two cores do it, one half at a time, and in strips.
+            let mut columns = [None; WIDTH as usize];
+            let (left, right) = columns.split_at_mut(half as usize);
+            raycaster.cast_column_range(0..half, left);
+            raycaster.cast_column_range(half..WIDTH, right);
+            let mut split_sprites = sprites();
+            renderer().sort_sprites(&raycaster, &mut split_sprites);
+            let mut split = vec![0; WIDTH as usize * HEIGHT as usize];
+            for (i, buffer) in split
+                .chunks_mut(WIDTH as usize * strip as usize)
+                .enumerate()
+            {
+                let rows = i as u16 * strip..(i as u16 + 1) * strip;
+                let region = Region::new(buffer, WIDTH, rows);
+                let (mut left, mut right) = region.split_at_column(half);
+                renderer().render_region(&raycaster, &columns, &split_sprites, &mut right);
+                renderer().render_region(&raycaster, &columns, &split_sprites, &mut left);
+            }
+
+            assert!(columns == whole_columns);
+            assert!(split == whole, "{} {} {}", x, y, degrees);
+        }
+    }
+
+    #[test]
+    fn windows_match_the_same_pixels_of_a_whole_frame() {
+        let mut storage = MapStorage::<{ levels::MAX_CELLS }>::new();
+        let (map, player) = parse_map(levels::DEMO, &mut storage).unwrap();
+        let mut raycaster = Raycaster::new(map);
+        raycaster.player = player;
+        let mut sprites: Vec<Sprite> = levels::DEMO_SPAWNS
+            .iter()
+            .filter_map(levels::sprite)
+            .collect();
+        let mut columns = [None; WIDTH as usize];
+        let mut whole = vec![0; WIDTH as usize * HEIGHT as usize];
+        renderer().render(&raycaster, &mut columns, &mut sprites, &mut whole);
+
+        for &(x, y, width, height) in &[(0, 0, 240, 1), (17, 100, 100, 33), (200, 3, 40, 237)] {
+            let mut window = vec![0; width as usize * height as usize];
+            let region = Region::window(&mut window, x..x + width, y..y + height);
+            // Split like the two cores do, even where the split is outside of the window.
+            let (mut left, mut right) = region.split_at_column(WIDTH / 2);
+            renderer().render_region(&raycaster, &columns, &sprites, &mut left);
+            renderer().render_region(&raycaster, &columns, &sprites, &mut right);
+            for (row, pixels) in window.chunks(width as usize).enumerate() {
+                let start = (y as usize + row) * WIDTH as usize + x as usize;
+                assert!(pixels == &whole[start..start + width as usize]);
+            }
+        }
+    }
+
+    #[test]
+    fn floors_and_ceilings_sample_each_cells_texture() {
+        // Draws the floor and ceiling of an open 3x3 map, looking east from the middle of its
+        // west edge, so the center column sees cells (1, 1), then (2, 1), then past the map.
+        let draw = |floors: &[u8], ceilings: &[u8]| -> Vec<u16> {
+            let map = Map::new(3, 3, &[0; 9], floors, ceilings);
+            let mut raycaster = Raycaster::new(map);
+            raycaster.player.x = Fixed::HALF;
+            raycaster.player.y = Fixed::from_f32(1.5);
+            raycaster.player.angle = Angle::ZERO;
+            let mut frame = vec![0; WIDTH as usize * HEIGHT as usize];
+            let mut region = Region::new(&mut frame, WIDTH, 0..HEIGHT);
+            renderer().draw_floor_and_ceiling(&raycaster, &mut region);
+            frame
+        };
+        #[rustfmt::skip]
+        let floors = [
+            0, 0, 0,
+            0, 1, 0,
+            0, 0, 0,
+        ];
+        #[rustfmt::skip]
+        let ceilings = [
+            0, 0, 0,
+            0, 2, 0,
+            0, 0, 0,
+        ];
+        let frame = draw(&floors, &ceilings);
+        // Ceiling rows see the same spots as the floor rows mirrored below the horizon, so
+        // floors of a single texture show what every row should sample.
+        let floor_of = |flat| draw(&[flat; 9], &[0; 9]);
+        let (plain, planks, panels) = (floor_of(0), floor_of(1), floor_of(2));
+
+        let column = WIDTH as usize / 2;
+        let pixel = |frame: &[u16], row: u16| frame[row as usize * WIDTH as usize + column];
+        // With the eye 104 pixels above the floor, cell (1, 1) fills the rows at least 69 rows
+        // below the horizon, cell (2, 1) the rows 42 to 68 below it, and past that lies the
+        // outside of the map. Ceiling rows mirror them above the horizon.
+        for below in 0..HEIGHT / 2 {
+            let floor_row = HEIGHT / 2 + below;
+            let ceiling_row = HEIGHT / 2 - 1 - below;
+            let (floor, ceiling) = match below {
+                75.. => (pixel(&planks, floor_row), pixel(&panels, floor_row)),
+                45..=65 => (pixel(&plain, floor_row), pixel(&plain, floor_row)),
+                0..=38 => (COLOR_DARKGRAY, COLOR_GRAY),
+                // Too close to a cell edge to tell.
+                _ => continue,
+            };
+            assert_eq!(pixel(&frame, floor_row), floor, "floor row {floor_row}");
+            assert_eq!(
+                pixel(&frame, ceiling_row),
+                ceiling,
+                "ceiling row {ceiling_row}"
+            );
+        }
+        // The textures differ where the cell is seen, so the rows above really sampled them.
+        let rows = || 200..HEIGHT;
+        assert!(rows().any(|row| pixel(&planks, row) != pixel(&plain, row)));
+        assert!(rows().any(|row| pixel(&panels, row) != pixel(&plain, row)));
+        assert!(rows().any(|row| pixel(&panels, row) != pixel(&planks, row)));
+    }
+}
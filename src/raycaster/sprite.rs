@@ -0,0 +1,25 @@
+use super::{Fixed, Texture};
+
+/// An object drawn as a billboard that always faces the player. Sprites are one tile wide and
+/// one tile tall, and stand on the floor.
+///
+/// Pixels in the texture set to [`super::TRANSPARENT`] are not drawn.
+pub struct Sprite {
+    pub x: Fixed,
+    pub y: Fixed,
+    pub texture: &'static Texture,
+}
+
+impl Sprite {
+    pub fn new(x: Fixed, y: Fixed, texture: &'static Texture) -> Self {
+        Self { x, y, texture }
+    }
+
+    /// Returns the squared distance to `x`, `y`, in fixed-point bits. Kept as an `i64` so it
+    /// can't overflow even across the largest maps.
+    pub fn squared_distance_bits(&self, x: Fixed, y: Fixed) -> i64 {
+        let dx = (self.x - x).to_bits() as i64;
+        let dy = (self.y - y).to_bits() as i64;
+        dx * dx + dy * dy
+    }
+}
@@ -0,0 +1,425 @@
+//! A plain text level format, meant to be embedded with `include_str!`.
+//!
+//! A level has a `[legend]` section, mapping characters to tiles, followed by a `[map]` section
+//! with one character per cell:
+//!
+//! ```text
+//! // Walls, then empty space with a wooden floor.
+//! [legend]
+//! # = 1 0 2
+//! . = 0 1 2
+//!
+//! [map]
+//! #####
+//! #>..#
+//! #####
+//! ```
+//!
+//! Each legend line is a character, `=`, and the cell's wall tile, optionally followed by its
+//! floor and ceiling values, which default to zero. Blank lines and lines starting with `//` are
+//! ignored outside of the map section.
+//!
+//! The map must hold exactly one player start marker, `^`, `>`, `v` or `<`, facing north, east,
+//! south or west. The start cell is always empty, and takes its floor and ceiling from the
+//! marker's legend entry if there is one.
+
+use core::fmt;
+
+use super::{Angle, Fixed, Map, MapStorage, Player};
+
+const LEGEND_HEADER: &str = "[legend]";
+const MAP_HEADER: &str = "[map]";
+const COMMENT: &str = "//";
+
+/// Errors found while parsing a text map. Lines and columns count from 1.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParseError {
+    /// A line outside of any section, or an unknown section header.
+    UnexpectedLine { line: usize },
+    /// A legend line isn't `<char> = <wall> [<floor> [<ceiling>]]`, or gives a wall to a player
+    /// start marker.
+    InvalidLegend { line: usize },
+    /// A character is defined twice in the legend.
+    DuplicateLegend { line: usize, symbol: char },
+    /// The `[map]` section is missing or has no rows.
+    MissingMap,
+    /// A map row is not as wide as the first one.
+    RaggedRow { line: usize },
+    /// A map character has no legend entry.
+    UnknownSymbol {
+        line: usize,
+        column: usize,
+        symbol: char,
+    },
+    /// The map has no player start marker.
+    MissingStart,
+    /// The map has more than one player start marker.
+    DuplicateStart { line: usize, column: usize },
+    /// The map has more cells than the storage can hold.
+    TooLarge { line: usize, capacity: usize },
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            ParseError::UnexpectedLine { line } => {
+                write!(f, "line {}: expected a section header", line)
+            }
+            ParseError::InvalidLegend { line } => write!(
+                f,
+                "line {}: expected `<char> = <wall> [<floor> [<ceiling>]]`",
+                line
+            ),
+            ParseError::DuplicateLegend { line, symbol } => {
+                write!(f, "line {}: `{}` is already in the legend", line, symbol)
+            }
+            ParseError::MissingMap => write!(f, "no `{}` section", MAP_HEADER),
+            ParseError::RaggedRow { line } => {
+                write!(f, "line {}: row width differs from the first row", line)
+            }
+            ParseError::UnknownSymbol {
+                line,
+                column,
+                symbol,
+            } => write!(
+                f,
+                "line {}, column {}: `{}` is not in the legend",
+                line, column, symbol
+            ),
+            ParseError::MissingStart => write!(f, "no player start marker"),
+            ParseError::DuplicateStart { line, column } => write!(
+                f,
+                "line {}, column {}: more than one player start marker",
+                line, column
+            ),
+            ParseError::TooLarge { line, capacity } => write!(
+                f,
+                "line {}: map is larger than the storage capacity of {} cells",
+                line, capacity
+            ),
+        }
+    }
+}
+
+/// What a legend character stands for.
+#[derive(Clone, Copy, Default)]
+struct Cell {
+    wall: u8,
+    floor: u8,
+    ceiling: u8,
+}
+
+enum Section {
+    None,
+    Legend,
+    Map,
+}
+
+/// Returns the direction a player start marker faces, if `symbol` is one.
+fn start_angle(symbol: char) -> Option<Angle> {
+    // Map rows go down the screen, so south is towards positive y.
+    match symbol {
+        '>' => Some(Angle::from_degrees(0)),
+        'v' => Some(Angle::from_degrees(90)),
+        '<' => Some(Angle::from_degrees(180)),
+        '^' => Some(Angle::from_degrees(270)),
+        _ => None,
+    }
+}
+
+/// Parses a legend line into its character and cell.
+fn parse_legend_line(text: &str, line: usize) -> Result<(char, Cell), ParseError> {
+    let invalid = ParseError::InvalidLegend { line };
+    let mut chars = text.chars();
+    let symbol = chars.next().ok_or(invalid)?;
+    let definition = chars
+        .as_str()
+        .trim_start()
+        .strip_prefix('=')
+        .ok_or(invalid)?;
+    if !symbol.is_ascii_graphic() {
+        return Err(invalid);
+    }
+
+    let mut values = [0; 3];
+    let mut count = 0;
+    for value in definition.split_whitespace() {
+        if count == values.len() {
+            return Err(invalid);
+        }
+        values[count] = value.parse().map_err(|_| invalid)?;
+        count += 1;
+    }
+    if count == 0 || (start_angle(symbol).is_some() && values[0] != 0) {
+        return Err(invalid);
+    }
+
+    let [wall, floor, ceiling] = values;
+    Ok((
+        symbol,
+        Cell {
+            wall,
+            floor,
+            ceiling,
+        },
+    ))
+}
+
+/// Parses a text map into `storage`, returning the map and the player standing at the center of
+/// the start cell. See the [module documentation](self) for the format.
+pub fn parse_map<'a, const N: usize>(
+    source: &str,
+    storage: &'a mut MapStorage<N>,
+) -> Result<(Map<'a>, Player), ParseError> {
+    // Legend entries, indexed by ASCII code.
+    let mut legend: [Option<Cell>; 128] = [None; 128];
+    let mut section = Section::None;
+    let mut width = 0;
+    let mut height = 0;
+    let mut start = None;
+    // Blank map lines are only allowed before the first row and after the last one.
+    let mut blank_line = None;
+
+    for (index, text) in source.lines().enumerate() {
+        let line = index + 1;
+        let text = text.trim_end_matches('\r');
+
+        if let Section::Map = section {
+            if text.is_empty() {
+                if height > 0 {
+                    blank_line = blank_line.or(Some(line));
+                }
+                continue;
+            }
+            if let Some(line) = blank_line {
+                return Err(ParseError::RaggedRow { line });
+            }
+
+            let row_width = text.chars().count();
+            if height == 0 {
+                width = row_width;
+            } else if row_width != width {
+                return Err(ParseError::RaggedRow { line });
+            }
+            if (height + 1) * width > N {
+                return Err(ParseError::TooLarge { line, capacity: N });
+            }
+
+            for (x, symbol) in text.chars().enumerate() {
+                let cell = legend.get(symbol as usize).copied().flatten();
+                let cell = match (start_angle(symbol), cell) {
+                    (Some(angle), cell) => {
+                        if start.is_some() {
+                            return Err(ParseError::DuplicateStart {
+                                line,
+                                column: x + 1,
+                            });
+                        }
+                        start = Some((x, height, angle));
+                        cell.unwrap_or_default()
+                    }
+                    (None, Some(cell)) => cell,
+                    (None, None) => {
+                        return Err(ParseError::UnknownSymbol {
+                            line,
+                            column: x + 1,
+                            symbol,
+                        })
+                    }
+                };
+                let index = height * width + x;
+                storage.cells[index] = cell.wall;
+                storage.floors[index] = cell.floor;
+                storage.ceilings[index] = cell.ceiling;
+            }
+            height += 1;
+            continue;
+        }
+
+        let text = text.trim();
+        if text.is_empty() || text.starts_with(COMMENT) {
+            continue;
+        }
+        if text == LEGEND_HEADER {
+            section = Section::Legend;
+        } else if text == MAP_HEADER {
+            section = Section::Map;
+        } else if let Section::Legend = section {
+            let (symbol, cell) = parse_legend_line(text, line)?;
+            let entry = &mut legend[symbol as usize];
+            if entry.is_some() {
+                return Err(ParseError::DuplicateLegend { line, symbol });
+            }
+            *entry = Some(cell);
+        } else {
+            return Err(ParseError::UnexpectedLine { line });
+        }
+    }
+
+    if height == 0 {
+        return Err(ParseError::MissingMap);
+    }
+    let (x, y, angle) = start.ok_or(ParseError::MissingStart)?;
+    let player = Player::at(
+        Fixed::from_int(x as i32) + Fixed::HALF,
+        Fixed::from_int(y as i32) + Fixed::HALF,
+        angle,
+    );
+    Ok((storage.map(width, height), player))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::string::String;
+
+    use super::*;
+
+    fn parse(source: &str) -> Result<(Map<'static>, Player), ParseError> {
+        let storage = std::boxed::Box::leak(std::boxed::Box::new(MapStorage::<64>::new()));
+        parse_map(source, storage)
+    }
+
+    fn error(source: &str) -> ParseError {
+        parse(source).err().unwrap()
+    }
+
+    #[test]
+    fn parses_cells_floors_and_ceilings() {
+        let source = "// Comment\r\n[legend]\r\n# = 1 0 2\r\n. = 0 1\r\n\r\n[map]\r\n\r\n####\r\n#.>#\r\n####\r\n\r\n";
+        let (map, _) = parse(source).unwrap();
+        assert_eq!((map.width, map.height), (4, 3));
+        assert_eq!(map.cells, [1, 1, 1, 1, 1, 0, 0, 1, 1, 1, 1, 1]);
+        assert_eq!(map.floors, [0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(map.ceilings, [2, 2, 2, 2, 2, 0, 0, 2, 2, 2, 2, 2]);
+    }
+
+    #[test]
+    fn parses_the_demo_level() {
+        let mut storage = MapStorage::<{ crate::levels::MAX_CELLS }>::new();
+        let (map, _) = parse_map(crate::levels::DEMO, &mut storage).unwrap();
+        assert_eq!((map.width, map.height), (10, 10));
+    }
+
+    #[test]
+    fn player_starts_at_the_center_of_the_marker() {
+        let (map, player) =
+            parse("[legend]\n# = 1\n. = 0\nv = 0 3 4\n[map]\n####\n#.v#\n####").unwrap();
+        assert_eq!(player.x, Fixed::from_int(2) + Fixed::HALF);
+        assert_eq!(player.y, Fixed::from_int(1) + Fixed::HALF);
+        assert_eq!(player.angle, Angle::from_degrees(90));
+        // The marker's cell is empty, with the marker's floor and ceiling.
+        assert_eq!(map.cells[6], 0);
+        assert_eq!((map.floors[6], map.ceilings[6]), (3, 4));
+    }
+
+    #[test]
+    fn start_markers_face_their_direction() {
+        for (marker, degrees) in [('>', 0), ('v', 90), ('<', 180), ('^', 270)] {
+            let mut source = String::from("[legend]\n# = 1\n[map]\n#");
+            source.push(marker);
+            let (_, player) = parse(&source).unwrap();
+            assert_eq!(player.angle, Angle::from_degrees(degrees));
+        }
+    }
+
+    #[test]
+    fn rejects_unexpected_lines() {
+        assert_eq!(error("#>#"), ParseError::UnexpectedLine { line: 1 });
+        assert_eq!(
+            error("// Comment\n\n[tiles]"),
+            ParseError::UnexpectedLine { line: 3 }
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_legends() {
+        let invalid = ParseError::InvalidLegend { line: 2 };
+        assert_eq!(error("[legend]\n# 1"), invalid);
+        assert_eq!(error("[legend]\n# ="), invalid);
+        assert_eq!(error("[legend]\n# = 1 2 3 4"), invalid);
+        assert_eq!(error("[legend]\n# = 256"), invalid);
+        assert_eq!(error("[legend]\n# = wall"), invalid);
+        assert_eq!(error("[legend]\n> = 1"), invalid);
+    }
+
+    #[test]
+    fn rejects_duplicate_legends() {
+        assert_eq!(
+            error("[legend]\n# = 1\n# = 2"),
+            ParseError::DuplicateLegend {
+                line: 3,
+                symbol: '#'
+            }
+        );
+    }
+
+    #[test]
+    fn rejects_missing_maps() {
+        assert_eq!(error("[legend]\n# = 1"), ParseError::MissingMap);
+        assert_eq!(error("[legend]\n# = 1\n[map]\n\n"), ParseError::MissingMap);
+    }
+
+    #[test]
+    fn rejects_ragged_rows() {
+        assert_eq!(
+            error("[legend]\n# = 1\n[map]\n##\n#>#"),
+            ParseError::RaggedRow { line: 5 }
+        );
+        // A blank line within the map counts as a row of the wrong width.
+        assert_eq!(
+            error("[legend]\n# = 1\n[map]\n##\n\n#>"),
+            ParseError::RaggedRow { line: 5 }
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_symbols() {
+        assert_eq!(
+            error("[legend]\n# = 1\n[map]\n#x>"),
+            ParseError::UnknownSymbol {
+                line: 4,
+                column: 2,
+                symbol: 'x'
+            }
+        );
+        assert_eq!(
+            error("[legend]\n# = 1\n[map]\n#é>"),
+            ParseError::UnknownSymbol {
+                line: 4,
+                column: 2,
+                symbol: 'é'
+            }
+        );
+    }
+
+    #[test]
+    fn rejects_missing_starts() {
+        assert_eq!(
+            error("[legend]\n# = 1\n[map]\n##"),
+            ParseError::MissingStart
+        );
+    }
+
+    #[test]
+    fn rejects_duplicate_starts() {
+        assert_eq!(
+            error("[legend]\n# = 1\n[map]\n>#<"),
+            ParseError::DuplicateStart { line: 4, column: 3 }
+        );
+    }
+
+    #[test]
+    fn rejects_maps_larger_than_the_storage() {
+        let mut source = String::from("[legend]\n# = 1\n[map]\n>#######\n");
+        for _ in 0..8 {
+            source.push_str("########\n");
+        }
+        assert_eq!(
+            error(&source),
+            ParseError::TooLarge {
+                line: 12,
+                capacity: 64
+            }
+        );
+    }
+}
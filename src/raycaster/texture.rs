@@ -0,0 +1,69 @@
+use super::Fixed;
+
+/// Packs an 8-bit per channel color into RGB565, in the same byte order as the frame buffer.
+pub const fn rgb565(r: u8, g: u8, b: u8) -> u16 {
+    u16::from_be(((r as u16 & 0xf8) << 8) | ((g as u16 & 0xfc) << 3) | (b as u16 >> 3))
+}
+
+/// Color key for transparent pixels in sprite textures.
+pub const TRANSPARENT: u16 = rgb565(0xff, 0x00, 0xff);
+
+/// Halves the brightness of an RGB565 color in frame buffer byte order.
+pub const fn darken(color: u16) -> u16 {
+    u16::from_be((u16::to_be(color) >> 1) & 0x7bef)
+}
+
+/// An RGB565 image, stored row by row in the same byte order as the frame buffer.
+///
+/// Pixels are borrowed for `'static`, so textures can be declared as `static` items and stay
+/// in flash instead of taking up RAM.
+pub struct Texture {
+    pub width: u16,
+    pub height: u16,
+    pub pixels: &'static [u16],
+}
+
+impl Texture {
+    pub const fn new(width: u16, height: u16, pixels: &'static [u16]) -> Self {
+        assert!(pixels.len() == width as usize * height as usize);
+        Self {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    /// Returns the pixel at `x`, `y`, wrapping around when the coordinates are out of bounds.
+    pub fn pixel(&self, x: i32, y: i32) -> u16 {
+        let x = x.rem_euclid(self.width as i32) as usize;
+        let y = y.rem_euclid(self.height as i32) as usize;
+        self.pixels[y * self.width as usize + x]
+    }
+
+    /// Maps a coordinate in `[0, 1)` across the texture width to a column.
+    pub fn column(&self, u: Fixed) -> i32 {
+        (u * self.width as i32).to_int()
+    }
+
+    /// Maps a coordinate in `[0, 1)` across the texture height to a row.
+    pub fn row(&self, v: Fixed) -> i32 {
+        (v * self.height as i32).to_int()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static PIXELS: [u16; 6] = [0, 1, 2, 3, 4, 5];
+    static TEXTURE: Texture = Texture::new(3, 2, &PIXELS);
+
+    #[test]
+    fn pixels_wrap_around() {
+        assert_eq!(TEXTURE.pixel(1, 1), 4);
+        assert_eq!(TEXTURE.pixel(4, 0), 1);
+        assert_eq!(TEXTURE.pixel(-1, 0), 2);
+        assert_eq!(TEXTURE.pixel(0, -1), 3);
+        assert_eq!(TEXTURE.pixel(5, 3), 5);
+    }
+}
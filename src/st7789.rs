@@ -2,6 +2,10 @@ use cortex_m::delay::Delay;
 use display_interface::{DataFormat, DisplayError, WriteOnlyDataCommand};
 use embedded_hal::digital::v2::OutputPin;
 
+use raycaster_embedded_rs::dirty::Rect;
+use raycaster_embedded_rs::double_buffer::FrameSink;
+use raycaster_embedded_rs::panel::PanelConfig;
+
 const ST7789_SWRESET: u8 = 0x01;
 const ST7789_SLPIN: u8 = 0x10;
 const ST7789_SLPOUT: u8 = 0x11;
@@ -29,6 +33,7 @@ pub struct St7789<DI: WriteOnlyDataCommand, RST: OutputPin> {
     display_interface: DI,
     pin_rst: Option<RST>,
     delay: Delay,
+    config: PanelConfig,
 }
 
 #[derive(Debug)]
@@ -44,11 +49,17 @@ impl<PE> From<DisplayError> for St7789Error<PE> {
 }
 
 impl<DI: WriteOnlyDataCommand, RST: OutputPin> St7789<DI, RST> {
-    pub fn new(display_interface: DI, pin_rst: Option<RST>, delay: Delay) -> Self {
+    pub fn new(
+        display_interface: DI,
+        pin_rst: Option<RST>,
+        delay: Delay,
+        config: PanelConfig,
+    ) -> Self {
         Self {
             display_interface,
             pin_rst,
             delay,
+            config,
         }
     }
 
@@ -61,14 +72,12 @@ impl<DI: WriteOnlyDataCommand, RST: OutputPin> St7789<DI, RST> {
         self.sleep_mode(false)?;
         self.delay.delay_ms(50);
 
-        // TODO(andreban): always using 240x240. Prepare for 240x135
-        //   if(width == 240 && height == 240) {
-        self.send_command(ST7789_MADCTL, Some(&[0x04]))?; // row/column addressing order - rgb pixel order
+        let madctl = self.config.madctl();
+        self.send_command(ST7789_MADCTL, Some(&[madctl]))?; // rotation, color order and refresh order
         self.send_command(ST7789_TEON, Some(&[0x00]))?; // enable frame sync signal if used
-                                                        //
 
         self.set_color_mode(COLOR_MODE_16BIT)?;
-        self.set_invert_mode(true)?;
+        self.set_invert_mode(self.config.invert)?;
         self.delay.delay_ms(10);
 
         self.send_command(ST7789_NORON, None)?;
@@ -77,10 +86,7 @@ impl<DI: WriteOnlyDataCommand, RST: OutputPin> St7789<DI, RST> {
         self.send_command(ST7789_DISPON, None)?;
 
         // setup correct addressing window
-        // if(width == 240 && height == 240) {
-        self.send_command(ST7789_CASET, Some(&[0x00, 0x00, 0x00, 0xef]))?; // 0 .. 239 columns
-        self.send_command(ST7789_RASET, Some(&[0x00, 0x00, 0x00, 0xef]))?; // 0 .. 239 rows
-                                                                           // }
+        self.set_window(self.config.screen())?;
         Ok(())
     }
 
@@ -134,10 +140,52 @@ impl<DI: WriteOnlyDataCommand, RST: OutputPin> St7789<DI, RST> {
         Ok(())
     }
 
-    pub fn set_pixels(&mut self, data: &[u16; 240 * 240]) -> Result<(), DisplayError> {
+    /// Writes a whole frame. `data` must hold `width * height` pixels.
+    // Blocking writes are kept for code without a DMA channel to spare. The game sends its
+    // frames through `FrameSink` instead.
+    #[allow(dead_code)]
+    pub fn set_pixels(&mut self, data: &[u16]) -> Result<(), DisplayError> {
+        self.write_region(self.config.screen(), data)
+    }
+
+    /// Sets the rectangle that the next pixels are written to, row by row from its top left
+    /// corner.
+    pub fn set_window(&mut self, rect: Rect) -> Result<(), DisplayError> {
+        if rect.is_empty() || rect.intersection(&self.config.screen()) != rect {
+            return Err(DisplayError::OutOfBoundsError);
+        }
+        let (columns, rows) = self.config.window(rect);
+        self.send_command(ST7789_CASET, Some(&columns))?;
+        self.send_command(ST7789_RASET, Some(&rows))?;
+        Ok(())
+    }
+
+    /// Writes `data` to `rect`, row by row. `data` must hold exactly one pixel for each pixel of
+    /// `rect`, so updating a small part of the screen sends only that part.
+    #[allow(dead_code)] // See `set_pixels`.
+    pub fn write_region(&mut self, rect: Rect, data: &[u16]) -> Result<(), DisplayError> {
+        if data.len() != rect.area() as usize {
+            return Err(DisplayError::InvalidFormatError);
+        }
+        self.set_window(rect)?;
         self.display_interface
             .send_commands(DataFormat::U8(&[ST7789_RAMWR]))?;
         self.display_interface.send_data(DataFormat::U16(data))?;
         Ok(())
     }
 }
+
+impl<DI: WriteOnlyDataCommand, RST: OutputPin> FrameSink for St7789<DI, RST> {
+    type Error = DisplayError;
+
+    /// Sets the window to `rect`, and sends RAMWR. The pixels must then be sent on the same bus
+    /// with chip select kept low, since only the command goes through the display interface.
+    fn begin_region(&mut self, rect: Rect) -> Result<(), DisplayError> {
+        self.set_window(rect)?;
+        self.display_interface
+            .send_commands(DataFormat::U8(&[ST7789_RAMWR]))?;
+        // Sending no data leaves the D/C line high, so the pixels are taken as data.
+        self.display_interface.send_data(DataFormat::U8(&[]))?;
+        Ok(())
+    }
+}
@@ -0,0 +1,24 @@
+//! Fixtures shared by the unit tests.
+
+use std::vec;
+use std::vec::Vec;
+
+use crate::raycaster::Map;
+
+/// Builds a map from rows of `#` for walls and `.` for empty cells, with every floor and
+/// ceiling set to zero. Its layers are leaked, so it can be kept as long as needed.
+pub fn map(rows: &[&str]) -> Map<'static> {
+    let width = rows[0].len();
+    let cells: Vec<u8> = rows
+        .iter()
+        .flat_map(|row| row.bytes())
+        .map(|symbol| (symbol == b'#') as u8)
+        .collect();
+    let flats: &'static [u8] = vec![0; cells.len()].leak();
+    Map::new(width, rows.len(), cells.leak(), flats, flats)
+}
+
+/// Returns a zeroed buffer that lives forever, as DMA transfers need.
+pub fn buffer(len: usize) -> &'static mut [u16] {
+    vec![0; len].leak()
+}
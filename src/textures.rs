@@ -0,0 +1,194 @@
+//! Built-in textures. They are generated at compile time, so they end up in flash like any other
+//! `static` data.
+
+use crate::raycaster::{rgb565, Texture, TRANSPARENT};
+
+const SIZE: usize = 32;
+
+const MORTAR: u16 = rgb565(0xa0, 0xa0, 0x98);
+const BRICK: u16 = rgb565(0xa0, 0x38, 0x28);
+const BRICK_DARK: u16 = rgb565(0x80, 0x28, 0x20);
+const STONE: u16 = rgb565(0x78, 0x78, 0x80);
+const STONE_LIGHT: u16 = rgb565(0x98, 0x98, 0xa0);
+const STONE_DARK: u16 = rgb565(0x40, 0x40, 0x48);
+const WOOD: u16 = rgb565(0x90, 0x60, 0x30);
+const WOOD_DARK: u16 = rgb565(0x60, 0x40, 0x20);
+const TILE_LIGHT: u16 = rgb565(0xc8, 0xc0, 0xb0);
+const TILE_DARK: u16 = rgb565(0x50, 0x48, 0x40);
+const PANEL: u16 = rgb565(0x58, 0x58, 0x60);
+const PANEL_EDGE: u16 = rgb565(0x30, 0x30, 0x38);
+const METAL: u16 = rgb565(0x70, 0x70, 0x78);
+const LIGHT: u16 = rgb565(0xff, 0xf0, 0xa0);
+const LIGHT_GLOW: u16 = rgb565(0xe0, 0xb0, 0x40);
+
+/// Red bricks, 8 pixels tall, with every other row offset by half a brick.
+const fn bricks() -> [u16; SIZE * SIZE] {
+    let mut pixels = [0; SIZE * SIZE];
+    let mut y = 0;
+    while y < SIZE {
+        let offset = if (y / 8) % 2 == 0 { 0 } else { 8 };
+        let mut x = 0;
+        while x < SIZE {
+            pixels[y * SIZE + x] = if y % 8 == 0 || (x + offset) % 16 == 0 {
+                MORTAR
+            } else if y % 8 == 7 {
+                BRICK_DARK
+            } else {
+                BRICK
+            };
+            x += 1;
+        }
+        y += 1;
+    }
+    pixels
+}
+
+/// Large grey blocks with a bevelled edge.
+const fn stone_blocks() -> [u16; SIZE * SIZE] {
+    let mut pixels = [0; SIZE * SIZE];
+    let mut y = 0;
+    while y < SIZE {
+        let mut x = 0;
+        while x < SIZE {
+            let (bx, by) = (x % 16, y % 16);
+            pixels[y * SIZE + x] = if bx == 0 || by == 0 {
+                STONE_LIGHT
+            } else if bx == 15 || by == 15 {
+                STONE_DARK
+            } else {
+                STONE
+            };
+            x += 1;
+        }
+        y += 1;
+    }
+    pixels
+}
+
+/// Vertical wooden planks.
+const fn planks() -> [u16; SIZE * SIZE] {
+    let mut pixels = [0; SIZE * SIZE];
+    let mut y = 0;
+    while y < SIZE {
+        let mut x = 0;
+        while x < SIZE {
+            // A knot-like grain line that shifts along each plank.
+            let grain = (y + (x / 8) * 11) % 13 == 0 && x % 8 == 3;
+            pixels[y * SIZE + x] = if x % 8 == 0 || grain { WOOD_DARK } else { WOOD };
+            x += 1;
+        }
+        y += 1;
+    }
+    pixels
+}
+
+/// A checkerboard of square floor tiles.
+const fn floor_tiles() -> [u16; SIZE * SIZE] {
+    let mut pixels = [0; SIZE * SIZE];
+    let mut y = 0;
+    while y < SIZE {
+        let mut x = 0;
+        while x < SIZE {
+            pixels[y * SIZE + x] = if (x / 16 + y / 16) % 2 == 0 {
+                TILE_LIGHT
+            } else {
+                TILE_DARK
+            };
+            x += 1;
+        }
+        y += 1;
+    }
+    pixels
+}
+
+/// Square ceiling panels with a dark frame.
+const fn ceiling_panels() -> [u16; SIZE * SIZE] {
+    let mut pixels = [0; SIZE * SIZE];
+    let mut y = 0;
+    while y < SIZE {
+        let mut x = 0;
+        while x < SIZE {
+            pixels[y * SIZE + x] = if x % 16 < 2 || y % 16 < 2 {
+                PANEL_EDGE
+            } else {
+                PANEL
+            };
+            x += 1;
+        }
+        y += 1;
+    }
+    pixels
+}
+
+/// A wooden barrel with metal hoops, standing on the floor.
+const fn barrel() -> [u16; SIZE * SIZE] {
+    let mut pixels = [TRANSPARENT; SIZE * SIZE];
+    let mut y = 14;
+    while y < SIZE {
+        // Slightly wider in the middle than at the top and bottom.
+        let bulge = if y > 18 && y < 28 { 1 } else { 0 };
+        let mut x = 9 - bulge;
+        while x < 23 + bulge {
+            pixels[y * SIZE + x] = if y == 16 || y == 29 {
+                METAL
+            } else if x % 4 == 0 {
+                WOOD_DARK
+            } else {
+                WOOD
+            };
+            x += 1;
+        }
+        y += 1;
+    }
+    pixels
+}
+
+/// A round ceiling-height lamp on a thin pole.
+const fn lamp() -> [u16; SIZE * SIZE] {
+    let mut pixels = [TRANSPARENT; SIZE * SIZE];
+    let mut y = 0;
+    while y < SIZE {
+        let mut x = 0;
+        while x < SIZE {
+            let (dx, dy) = (x as i32 - 16, y as i32 - 8);
+            let distance = dx * dx + dy * dy;
+            pixels[y * SIZE + x] = if distance < 16 {
+                LIGHT
+            } else if distance < 36 {
+                LIGHT_GLOW
+            } else if (x == 15 || x == 16) && y > 8 {
+                METAL
+            } else {
+                TRANSPARENT
+            };
+            x += 1;
+        }
+        y += 1;
+    }
+    pixels
+}
+
+static BRICKS: [u16; SIZE * SIZE] = bricks();
+static STONE_BLOCKS: [u16; SIZE * SIZE] = stone_blocks();
+static PLANKS: [u16; SIZE * SIZE] = planks();
+static FLOOR_TILES: [u16; SIZE * SIZE] = floor_tiles();
+static CEILING_PANELS: [u16; SIZE * SIZE] = ceiling_panels();
+static BARREL: [u16; SIZE * SIZE] = barrel();
+static LAMP: [u16; SIZE * SIZE] = lamp();
+
+/// Wall textures, indexed by map tile value minus one.
+pub static WALLS: [Texture; 3] = [
+    Texture::new(SIZE as u16, SIZE as u16, &BRICKS),
+    Texture::new(SIZE as u16, SIZE as u16, &STONE_BLOCKS),
+    Texture::new(SIZE as u16, SIZE as u16, &PLANKS),
+];
+
+/// Floor and ceiling textures, indexed by the map's floor and ceiling values.
+pub static FLATS: [Texture; 3] = [
+    Texture::new(SIZE as u16, SIZE as u16, &FLOOR_TILES),
+    Texture::new(SIZE as u16, SIZE as u16, &PLANKS),
+    Texture::new(SIZE as u16, SIZE as u16, &CEILING_PANELS),
+];
+
+pub static BARREL_SPRITE: Texture = Texture::new(SIZE as u16, SIZE as u16, &BARREL);
+pub static LAMP_SPRITE: Texture = Texture::new(SIZE as u16, SIZE as u16, &LAMP);